@@ -1,4 +1,4 @@
-use clap::{App, Arg};
+use clap::{App, Arg, SubCommand};
 
 pub const TIME_FORMAT_DEFAULT: &str = "%I:%M %p";
 
@@ -24,11 +24,185 @@ pub fn build_cli() -> App<'static, 'static> {
                 .value_name("int")
                 .default_value("15")
         )
+        .arg(
+            Arg::with_name("cwd-max-length")
+                .long("cwd-max-length")
+                .help("Maximum width of the whole path. \
+                       Directories are shortened, then left out, and then the current one is cut off to fit. \
+                       Setting this to 0 means unlimited.")
+                .takes_value(true)
+                .value_name("int")
+                .default_value("0")
+        )
+        .arg(
+            Arg::with_name("cwd-mode")
+                .long("cwd-mode")
+                .help("How to show the directories leading up to the current one. \
+                       fish shortens each to its first letter, \
+                       unique to the shortest prefix no other directory next to it starts with. \
+                       repo shows the path inside the current git repository instead, if any. \
+                       plain shows the whole path as one segment.")
+                .takes_value(true)
+                .value_name("string")
+                .possible_values(&["full", "fish", "unique", "repo", "plain"])
+                .default_value("full")
+        )
+        .arg(
+            Arg::with_name("cwd-named-dirs")
+                .long("cwd-named-dirs")
+                .help("Named directories to show as ~name, one name=path per line. \
+                       Pass \"$(hash -d)\" from zsh to use its named directories.")
+                .takes_value(true)
+                .value_name("string")
+        )
+        .arg(
+            Arg::with_name("cwd-separator")
+                .long("cwd-separator")
+                .help("What joins the directories when --cwd-mode is plain")
+                .takes_value(true)
+                .value_name("string")
+                .default_value("/")
+        )
         .arg(
             Arg::with_name("error")
                 .help("Exit code of previously executed command")
                 .default_value("0")
         )
+        .arg(
+            Arg::with_name("git-branch-urls")
+                .long("git-branch-urls")
+                .help("Web pages the git branch links to with --hyperlinks, separated by ','. \
+                       Each is a glob matched against the remote URL, '=', and a template with {host}, {repo} and {branch}. \
+                       GitHub, GitLab and Bitbucket are known already. \
+                       Example: *git.example.com*=https://{host}/{repo}/src/branch/{branch}. \
+                       Not supported with --git-cli.")
+                .hidden(!cfg!(feature = "git2"))
+                .takes_value(true)
+                .value_name("string")
+                .value_delimiter(",")
+        )
+        .arg(
+            Arg::with_name("git-cli")
+                .long("git-cli")
+                .help("Parse the output of the git executable instead of using libgit2. \
+                       This is always the case when compiled without libgit2.")
+                .hidden(!cfg!(feature = "git2"))
+        )
+        .arg(
+            Arg::with_name("git-commit-format")
+                .long("git-commit-format")
+                .help("Template for the gitcommit module. \
+                       Supports {hash}, {age}, {initials} (of the author) and {subject}.")
+                .hidden(!cfg!(feature = "git2"))
+                .takes_value(true)
+                .value_name("string")
+                .default_value("{hash} {age}")
+        )
+        .arg(
+            Arg::with_name("git-commit-subject-length")
+                .long("git-commit-subject-length")
+                .help("Maximum number of letters displayed for the commit subject in gitcommit. \
+                       Setting this to 0 means unlimited.")
+                .hidden(!cfg!(feature = "git2"))
+                .takes_value(true)
+                .value_name("int")
+                .default_value("30")
+        )
+        .arg(
+            Arg::with_name("git-diff-max-files")
+                .long("git-diff-max-files")
+                .help("Maximum number of changed files the gitdiff module counts the lines of, \
+                       showing +? above it. Setting this to 0 means unlimited.")
+                .hidden(!cfg!(feature = "git2"))
+                .takes_value(true)
+                .value_name("int")
+                .default_value("1000")
+        )
+        .arg(
+            Arg::with_name("git-diff-split")
+                .long("git-diff-split")
+                .help("Show the staged and unstaged line counts of the gitdiff module separately")
+                .hidden(!cfg!(feature = "git2"))
+        )
+        .arg(
+            Arg::with_name("git-hide")
+                .long("git-hide")
+                .help("Paths where git segments of repositories from --git-work-trees are hidden, \
+                       as globs separated by ','. A * doesn't match a /, but ** does. \
+                       A leading ~ is the home directory. \
+                       Example: ~/**")
+                .takes_value(true)
+                .value_name("string")
+                .value_delimiter(",")
+        )
+        .arg(
+            Arg::with_name("git-identity")
+                .long("git-identity")
+                .help("Rules for the gitidentity module, separated by ','. \
+                       Each is a glob matched against the remote URL, '=', and the domain user.email should have. \
+                       Example: *github.com*=users.noreply.github.com")
+                .hidden(!cfg!(feature = "git2"))
+                .takes_value(true)
+                .value_name("string")
+                .value_delimiter(",")
+        )
+        .arg(
+            Arg::with_name("git-ignore-submodules")
+                .long("git-ignore-submodules")
+                .help("Which changes make a submodule count as dirty in gitstage, like git's --ignore-submodules. \
+                       Setting this to all skips checking submodules completely.")
+                .takes_value(true)
+                .value_name("string")
+                .possible_values(&["none", "untracked", "dirty", "all"])
+                .default_value("none")
+        )
+        .arg(
+            Arg::with_name("git-indicators")
+                .long("git-indicators")
+                .help("Extra details to show in the git segment, separated by ','. \
+                       Not supported with --git-cli.")
+                .hidden(!cfg!(feature = "git2"))
+                .takes_value(true)
+                .value_name("string")
+                .possible_values(&["remote", "worktree", "submodule", "shallow", "sparse"])
+                .value_delimiter(",")
+        )
+        .arg(
+            Arg::with_name("git-jj")
+                .long("git-jj")
+                .help("What the git and gitstage modules do in a jj repository, where git only sees a detached HEAD. \
+                       annotate marks the git segment, hide leaves it to the jj module.")
+                .takes_value(true)
+                .value_name("string")
+                .possible_values(&["show", "annotate", "hide"])
+                .default_value("annotate")
+        )
+        .arg(
+            Arg::with_name("git-timeout")
+                .long("git-timeout")
+                .help("Maximum number of milliseconds to spend collecting git statuses. \
+                       When exceeded, the branch is shown with an unknown state. \
+                       Setting this to 0 means unlimited.")
+                .takes_value(true)
+                .value_name("int")
+                .default_value("0")
+        )
+        .arg(
+            Arg::with_name("git-work-trees")
+                .long("git-work-trees")
+                .help("Work trees of repositories kept elsewhere, like a bare dotfiles repository, separated by ','. \
+                       Each is the work tree, '=', and the git directory. \
+                       A repository nested in the work tree still takes precedence, and so does $GIT_DIR. \
+                       Example: ~=~/.dotfiles")
+                .takes_value(true)
+                .value_name("string")
+                .value_delimiter(",")
+        )
+        .arg(
+            Arg::with_name("hyperlinks")
+                .long("hyperlinks")
+                .help("Link the directories of cwd and the git branch using OSC 8, for terminals that support it")
+        )
         .arg(
             Arg::with_name("time_format")
                 .long("time_format")
@@ -48,6 +222,11 @@ pub fn build_cli() -> App<'static, 'static> {
                 .value_delimiter(",")
                 .default_value("ssh,cwd,perms,git,gitstage,nix-shell,root")
         )
+        .arg(
+            Arg::with_name("osc7")
+                .long("osc7")
+                .help("Report the current directory to the terminal, so new tabs can open in it")
+        )
         .arg(
             Arg::with_name("newline")
                 .long("newline")
@@ -69,9 +248,22 @@ pub fn build_cli() -> App<'static, 'static> {
                 .takes_value(true)
                 .value_name("file")
         )
+        .arg(
+            Arg::with_name("title")
+                .long("title")
+                .help("Set the terminal window title from a template, with {user}, {host}, {cwd} and {branch}. \
+                       Example: \"{user}@{host}: {cwd} {branch}\"")
+                .takes_value(true)
+                .value_name("string")
+        )
         .arg(
             Arg::with_name("rtl")
                 .long("rtl")
                 .help("Print everything from right to left")
             )
+        .subcommand(
+            SubCommand::with_name("daemon")
+                .about("Cache git statuses in the background, invalidated using inotify. \
+                        Prompts ask it over a socket in $XDG_RUNTIME_DIR whenever it's running.")
+        )
 }
@@ -0,0 +1,565 @@
+use crate::segments::{git_libgit2, IgnoreSubmodules};
+use git2::{Repository, Status};
+use std::{
+    collections::HashMap,
+    env,
+    ffi::CString,
+    fs::{self, File},
+    io::{self, prelude::*, BufReader},
+    os::{
+        raw::{c_char, c_int},
+        unix::{
+            ffi::OsStrExt,
+            io::FromRawFd,
+            net::{UnixListener, UnixStream}
+        }
+    },
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread,
+    time::Instant
+};
+
+const SOCKET_NAME: &str = "powerline-rs.sock";
+
+// Watching a giant worktree would eat through the inotify limits. Such
+// repositories are still served, but their statuses aren't cached.
+const MAX_WATCHES: usize = 8192;
+
+// Repositories that weren't asked about in a while are forgotten to give
+// their watches back
+const MAX_REPOS: usize = 16;
+
+const IN_CLOEXEC:     c_int = 0o2000000;
+const IN_MODIFY:      u32 = 0x0000_0002;
+const IN_ATTRIB:      u32 = 0x0000_0004;
+const IN_CLOSE_WRITE: u32 = 0x0000_0008;
+const IN_MOVED_FROM:  u32 = 0x0000_0040;
+const IN_MOVED_TO:    u32 = 0x0000_0080;
+const IN_CREATE:      u32 = 0x0000_0100;
+const IN_DELETE:      u32 = 0x0000_0200;
+const IN_DELETE_SELF: u32 = 0x0000_0400;
+const IN_MOVE_SELF:   u32 = 0x0000_0800;
+const IN_Q_OVERFLOW:  u32 = 0x0000_4000;
+const IN_IGNORED:     u32 = 0x0000_8000;
+const IN_ONLYDIR:     u32 = 0x0100_0000;
+const IN_ISDIR:       u32 = 0x4000_0000;
+
+const WATCH_MASK: u32 = IN_MODIFY | IN_ATTRIB | IN_CLOSE_WRITE | IN_MOVED_FROM | IN_MOVED_TO
+    | IN_CREATE | IN_DELETE | IN_DELETE_SELF | IN_MOVE_SELF | IN_ONLYDIR;
+
+extern "C" {
+    fn inotify_init1(flags: c_int) -> c_int;
+    fn inotify_add_watch(fd: c_int, pathname: *const c_char, mask: u32) -> c_int;
+    fn inotify_rm_watch(fd: c_int, wd: c_int) -> c_int;
+}
+
+pub fn socket_path() -> Option<PathBuf> {
+    let mut path = PathBuf::from(env::var_os("XDG_RUNTIME_DIR")?);
+    path.push(SOCKET_NAME);
+    Some(path)
+}
+
+pub enum Reply {
+    NotRunning,
+    TimedOut,
+    /// The statuses and the number of submodules with changes
+    Statuses(Option<(Vec<Status>, usize)>)
+}
+
+/// Ask a running daemon for the statuses of the repository at `git_dir`.
+pub fn query(git_dir: &Path, untracked: bool, ignore: IgnoreSubmodules, deadline: Option<Instant>) -> Reply {
+    let mut stream = match socket_path().and_then(|path| UnixStream::connect(path).ok()) {
+        Some(stream) => stream,
+        None => return Reply::NotRunning
+    };
+    if let Some(deadline) = deadline {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        if timeout.as_nanos() == 0 || stream.set_read_timeout(Some(timeout)).is_err() {
+            return Reply::TimedOut;
+        }
+    }
+
+    let mut request = Vec::with_capacity(git_dir.as_os_str().len() + 5);
+    request.extend_from_slice(if untracked { b"1 " } else { b"0 " });
+    request.push(b'0' + ignore as u8);
+    request.push(b' ');
+    request.extend_from_slice(git_dir.as_os_str().as_bytes());
+    request.push(b'\n');
+    if stream.write_all(&request).is_err() {
+        return Reply::NotRunning;
+    }
+
+    let mut reply = String::new();
+    match stream.read_to_string(&mut reply) {
+        Ok(_) => (),
+        Err(ref err) if err.kind() == io::ErrorKind::WouldBlock
+            || err.kind() == io::ErrorKind::TimedOut => return Reply::TimedOut,
+        Err(_) => return Reply::NotRunning
+    }
+
+    let mut lines = reply.lines();
+    match lines.next() {
+        Some("ok") => Reply::Statuses(lines.next()
+            .and_then(|submodules| submodules.parse().ok())
+            .and_then(|submodules| {
+                let statuses = lines
+                    .map(|line| line.parse().ok().map(Status::from_bits_truncate))
+                    .collect::<Option<_>>()?;
+                Some((statuses, submodules))
+            })),
+        Some(_) => Reply::Statuses(None),
+        // The daemon died on us
+        None => Reply::NotRunning
+    }
+}
+
+enum WatchKind {
+    /// The git directory, or the common directory of a linked worktree
+    GitDir,
+    /// A directory below refs/
+    Refs(PathBuf),
+    WorkDir(PathBuf)
+}
+struct Watch {
+    repo: PathBuf,
+    kind: WatchKind
+}
+struct Repo {
+    git: Arc<Mutex<Repository>>,
+    statuses: [Option<Vec<Status>>; 2],
+    /// Submodules with changes, by what's ignored in them
+    submodules: [Option<usize>; 4],
+    /// When the statuses were last invalidated, so a scan that raced with a
+    /// change isn't cached
+    generation: u64,
+    watches: usize,
+    complete: bool,
+    last_used: Instant
+}
+struct State {
+    inotify: c_int,
+    generation: u64,
+    repos: HashMap<PathBuf, Repo>,
+    watches: HashMap<c_int, Vec<Watch>>
+}
+
+fn add_watch(inotify: c_int, dir: &Path) -> Option<c_int> {
+    let dir = CString::new(dir.as_os_str().as_bytes()).ok()?;
+    let wd = unsafe { inotify_add_watch(inotify, dir.as_ptr(), WATCH_MASK) };
+    if wd < 0 { None } else { Some(wd) }
+}
+/// Watch `dir` and the directories below it, but no more than `budget` of
+/// them. Directories git ignores are skipped if `git` is given. Returns the
+/// watches and whether every directory got one.
+fn watch_tree(
+    inotify: c_int,
+    dir: &Path,
+    git: Option<&Repository>,
+    kind: fn(PathBuf) -> WatchKind,
+    budget: usize
+) -> (Vec<(c_int, WatchKind)>, bool) {
+    let workdir = git.and_then(Repository::workdir);
+    let mut watches = Vec::new();
+    let mut complete = true;
+    let mut queue = vec![dir.to_path_buf()];
+
+    while let Some(dir) = queue.pop() {
+        if watches.len() >= budget {
+            return (watches, false);
+        }
+        match add_watch(inotify, &dir) {
+            Some(wd) => watches.push((wd, kind(dir.clone()))),
+            None => {
+                complete = false;
+                continue;
+            }
+        }
+
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(_) => continue
+        };
+        for entry in entries.filter_map(Result::ok) {
+            if entry.file_name() == ".git" || !entry.file_type().map(|t| t.is_dir()).unwrap_or(false) {
+                continue;
+            }
+            let path = entry.path();
+            let ignored = git.and_then(|git| {
+                    let relative = path.strip_prefix(workdir?).ok()?;
+                    git.is_path_ignored(relative).ok()
+                })
+                .unwrap_or(false);
+            if !ignored {
+                queue.push(path);
+            }
+        }
+    }
+    (watches, complete)
+}
+
+impl State {
+    fn insert_watches(&mut self, key: &Path, watches: Vec<(c_int, WatchKind)>, complete: bool) {
+        let repo = match self.repos.get_mut(key) {
+            Some(repo) => repo,
+            None => return
+        };
+        repo.watches += watches.len();
+        repo.complete &= complete;
+        for (wd, kind) in watches {
+            self.watches.entry(wd).or_default().push(Watch {
+                repo: key.to_path_buf(),
+                kind
+            });
+        }
+    }
+    fn invalidate(&mut self, key: &Path) {
+        if let Some(repo) = self.repos.get_mut(key) {
+            self.generation += 1;
+            repo.statuses = [None, None];
+            repo.submodules = [None; 4];
+            repo.generation = self.generation;
+        }
+    }
+    /// Forget a repository and stop watching directories nobody else needs
+    fn remove_repo(&mut self, key: &Path) {
+        if self.repos.remove(key).is_none() {
+            return;
+        }
+        let inotify = self.inotify;
+        self.watches.retain(|&wd, watches| {
+            watches.retain(|watch| watch.repo != key);
+            if watches.is_empty() {
+                unsafe { inotify_rm_watch(inotify, wd); }
+                false
+            } else { true }
+        });
+    }
+    /// Handle an inotify event, returning the new directories to watch
+    fn handle_event(&mut self, wd: c_int, mask: u32, name: Option<&Path>) -> Vec<(PathBuf, WatchKind)> {
+        let mut new_dirs = Vec::new();
+        if mask & IN_Q_OVERFLOW == IN_Q_OVERFLOW {
+            // We missed events, so nothing can be trusted
+            let keys: Vec<_> = self.repos.keys().cloned().collect();
+            for key in keys {
+                self.invalidate(&key);
+            }
+            return new_dirs;
+        }
+        if mask & IN_IGNORED == IN_IGNORED {
+            for watch in self.watches.remove(&wd).unwrap_or_default() {
+                if let WatchKind::GitDir = watch.kind {
+                    // The repository is gone
+                    self.remove_repo(&watch.repo);
+                } else if let Some(repo) = self.repos.get_mut(&watch.repo) {
+                    repo.watches -= 1;
+                    self.invalidate(&watch.repo);
+                }
+            }
+            return new_dirs;
+        }
+
+        let mut invalidated = Vec::new();
+        for watch in self.watches.get(&wd).map(|watches| &watches[..]).unwrap_or_default() {
+            let relevant = match watch.kind {
+                // Only the index and refs affect the statuses. Everything
+                // else in there is changed by libgit2 itself all the time.
+                WatchKind::GitDir => name.map(|name| {
+                        name == Path::new("index") || name == Path::new("HEAD") || name == Path::new("packed-refs")
+                    })
+                    .unwrap_or(false),
+                WatchKind::Refs(ref dir) | WatchKind::WorkDir(ref dir) => {
+                    if let Some(name) = name {
+                        if name == Path::new(".git") {
+                            continue;
+                        }
+                        if mask & IN_ISDIR == IN_ISDIR && mask & (IN_CREATE | IN_MOVED_TO) != 0 {
+                            let dir = dir.join(name);
+                            new_dirs.push((watch.repo.clone(), match watch.kind {
+                                WatchKind::Refs(_) => WatchKind::Refs(dir),
+                                _ => WatchKind::WorkDir(dir)
+                            }));
+                        }
+                    }
+                    true
+                }
+            };
+            if relevant {
+                invalidated.push(watch.repo.clone());
+            }
+        }
+        for key in invalidated {
+            self.invalidate(&key);
+        }
+        new_dirs
+    }
+}
+
+/// The directory a linked worktree shares with the main one, found like git
+/// does it since libgit2 doesn't tell
+fn common_dir(git_dir: &Path) -> Option<PathBuf> {
+    let common_dir = fs::read_to_string(git_dir.join("commondir")).ok()?;
+    fs::canonicalize(git_dir.join(common_dir.trim_end())).ok()
+}
+/// Open a repository and start watching it, unless that was already done.
+/// The directories are walked without holding the lock, since that can take
+/// a while.
+fn open(state: &Mutex<State>, key: &Path) -> Option<Arc<Mutex<Repository>>> {
+    let inotify = {
+        let mut state = state.lock().unwrap();
+        if let Some(repo) = state.repos.get_mut(key) {
+            repo.last_used = Instant::now();
+            return Some(Arc::clone(&repo.git));
+        }
+        state.inotify
+    };
+    let git = Repository::open(key).ok()?;
+
+    // The git directory has the index and HEAD, and the common directory of
+    // a linked worktree has the refs shared by all of them
+    let mut watches = Vec::new();
+    let mut complete = true;
+    let mut git_dirs = vec![git.path().to_path_buf()];
+    git_dirs.extend(common_dir(git.path()));
+    for dir in &git_dirs {
+        match add_watch(inotify, dir) {
+            Some(wd) => watches.push((wd, WatchKind::GitDir)),
+            None => complete = false
+        }
+        let refs = dir.join("refs");
+        if refs.is_dir() {
+            let (refs, refs_complete) = watch_tree(inotify, &refs, None, WatchKind::Refs, MAX_WATCHES.saturating_sub(watches.len()));
+            watches.extend(refs);
+            complete &= refs_complete;
+        }
+    }
+    // A commit in a submodule only shows in its own git directory
+    if let Ok(submodules) = git.submodules() {
+        for submodule in submodules.iter().filter_map(|submodule| submodule.open().ok()) {
+            match add_watch(inotify, submodule.path()) {
+                Some(wd) => watches.push((wd, WatchKind::GitDir)),
+                None => complete = false
+            }
+        }
+    }
+    if let Some(workdir) = git.workdir() {
+        let budget = MAX_WATCHES.saturating_sub(watches.len());
+        let (workdir, workdir_complete) = watch_tree(inotify, workdir, Some(&git), WatchKind::WorkDir, budget);
+        watches.extend(workdir);
+        complete &= workdir_complete;
+    }
+
+    let mut state = state.lock().unwrap();
+    if let Some(repo) = state.repos.get_mut(key) {
+        // Someone else was quicker. Our watches are the same as theirs.
+        repo.last_used = Instant::now();
+        return Some(Arc::clone(&repo.git));
+    }
+    if state.repos.len() >= MAX_REPOS {
+        let oldest = state.repos.iter()
+            .min_by_key(|(_, repo)| repo.last_used)
+            .map(|(key, _)| key.clone());
+        if let Some(oldest) = oldest {
+            state.remove_repo(&oldest);
+        }
+    }
+    state.generation += 1;
+    let git = Arc::new(Mutex::new(git));
+    let repo = Repo {
+        git: Arc::clone(&git),
+        statuses: [None, None],
+        submodules: [None; 4],
+        generation: state.generation,
+        watches: 0,
+        complete,
+        last_used: Instant::now()
+    };
+    state.repos.insert(key.to_path_buf(), repo);
+    state.insert_watches(key, watches, complete);
+    Some(git)
+}
+fn statuses(state: &Mutex<State>, key: &Path, untracked: bool, ignore: IgnoreSubmodules) -> Option<(Vec<Status>, usize)> {
+    let git = open(state, key)?;
+    let (statuses, submodules, generation) = {
+        let state = state.lock().unwrap();
+        let repo = state.repos.get(key)?;
+        (repo.statuses[untracked as usize].clone(), repo.submodules[ignore as usize], repo.generation)
+    };
+    if let (Some(statuses), Some(submodules)) = (&statuses, submodules) {
+        return Some((statuses.clone(), submodules));
+    }
+
+    let (statuses, submodules) = {
+        let git = git.lock().unwrap();
+        let statuses = match statuses {
+            Some(statuses) => statuses,
+            None => git_libgit2::collect_statuses(&git, untracked)?
+        };
+        let submodules = match submodules {
+            Some(submodules) => submodules,
+            None => git_libgit2::count_submodules(&git, ignore, None)?
+        };
+        (statuses, submodules)
+    };
+
+    let mut state = state.lock().unwrap();
+    if let Some(repo) = state.repos.get_mut(key) {
+        if repo.complete && repo.generation == generation {
+            repo.statuses[untracked as usize] = Some(statuses.clone());
+            repo.submodules[ignore as usize] = Some(submodules);
+        }
+    }
+    Some((statuses, submodules))
+}
+
+fn watch_events(state: Arc<Mutex<State>>, mut inotify: File) {
+    let mut buf = [0u8; 4096];
+    loop {
+        let len = match inotify.read(&mut buf) {
+            Ok(len) => len,
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => {
+                eprintln!("failed to read inotify events: {}", err);
+                std::process::exit(1);
+            }
+        };
+
+        let mut new_dirs = Vec::new();
+        {
+            let mut state = state.lock().unwrap();
+            let mut i = 0;
+            // struct inotify_event { int wd; uint32_t mask, cookie, len; char name[]; }
+            while i + 16 <= len {
+                let field = |j: usize| {
+                    let mut bytes = [0; 4];
+                    bytes.copy_from_slice(&buf[i+j..i+j+4]);
+                    u32::from_ne_bytes(bytes)
+                };
+                let wd = field(0) as c_int;
+                let mask = field(4);
+                let name_len = field(12) as usize;
+
+                let name = &buf[i+16..(i+16+name_len).min(len)];
+                let name = &name[..name.iter().position(|&b| b == 0).unwrap_or(name.len())];
+                let name = if name.is_empty() {
+                    None
+                } else {
+                    Some(Path::new(std::ffi::OsStr::from_bytes(name)))
+                };
+
+                new_dirs.extend(state.handle_event(wd, mask, name));
+                i += 16 + name_len;
+            }
+        }
+
+        for (key, kind) in new_dirs {
+            watch_new_dir(&state, &key, kind);
+        }
+    }
+}
+fn watch_new_dir(state: &Mutex<State>, key: &Path, kind: WatchKind) {
+    let (inotify, budget) = {
+        let state = state.lock().unwrap();
+        match state.repos.get(key) {
+            Some(repo) => (state.inotify, MAX_WATCHES.saturating_sub(repo.watches)),
+            None => return
+        }
+    };
+    let (watches, complete) = match kind {
+        WatchKind::Refs(ref dir) => watch_tree(inotify, dir, None, WatchKind::Refs, budget),
+        WatchKind::WorkDir(ref dir) => match Repository::open(key) {
+            Ok(git) => watch_tree(inotify, dir, Some(&git), WatchKind::WorkDir, budget),
+            Err(_) => (Vec::new(), false)
+        },
+        WatchKind::GitDir => return
+    };
+
+    let mut state = state.lock().unwrap();
+    state.insert_watches(key, watches, complete);
+    // Anything created in there before the watch was added went unnoticed
+    state.invalidate(key);
+}
+
+fn serve(state: &Mutex<State>, stream: UnixStream) -> io::Result<()> {
+    let mut reader = BufReader::new(&stream);
+    let mut request = Vec::new();
+    reader.read_until(b'\n', &mut request)?;
+    if request.last() == Some(&b'\n') {
+        request.pop();
+    }
+
+    let mut stream = &stream;
+    if request.is_empty() {
+        // Just checking whether we're alive
+        return Ok(());
+    }
+    if request.len() < 4 || request[1] != b' ' || request[3] != b' ' {
+        return stream.write_all(b"err\n");
+    }
+    let untracked = request[0] == b'1';
+    let ignore = match request[2] {
+        b'0' => IgnoreSubmodules::None,
+        b'1' => IgnoreSubmodules::Untracked,
+        b'2' => IgnoreSubmodules::Dirty,
+        b'3' => IgnoreSubmodules::All,
+        _ => return stream.write_all(b"err\n")
+    };
+    let key = Path::new(std::ffi::OsStr::from_bytes(&request[4..]));
+
+    match statuses(state, key, untracked, ignore) {
+        Some((statuses, submodules)) => {
+            let mut reply = String::with_capacity(8 + statuses.len() * 4);
+            reply.push_str("ok\n");
+            reply.push_str(&submodules.to_string());
+            reply.push('\n');
+            for status in statuses {
+                reply.push_str(&status.bits().to_string());
+                reply.push('\n');
+            }
+            stream.write_all(reply.as_bytes())
+        },
+        None => stream.write_all(b"err\n")
+    }
+}
+
+pub fn run() -> Result<(), Box<dyn std::error::Error>> {
+    let path = socket_path().ok_or("$XDG_RUNTIME_DIR is not set")?;
+
+    if UnixStream::connect(&path).is_ok() {
+        return Err(format!("a daemon is already listening on {}", path.display()).into());
+    }
+    // Nobody's listening, so it's a leftover from a dead daemon
+    let _ = fs::remove_file(&path);
+    let listener = UnixListener::bind(&path)?;
+
+    let inotify = unsafe { inotify_init1(IN_CLOEXEC) };
+    if inotify < 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    let state = Arc::new(Mutex::new(State {
+        inotify,
+        generation: 0,
+        repos: HashMap::new(),
+        watches: HashMap::new()
+    }));
+
+    {
+        let state = Arc::clone(&state);
+        let inotify = unsafe { File::from_raw_fd(inotify) };
+        thread::spawn(move || watch_events(state, inotify));
+    }
+
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                // Scanning a large repository mustn't hold up everyone else
+                let state = Arc::clone(&state);
+                thread::spawn(move || if let Err(err) = serve(&state, stream) {
+                    eprintln!("failed to answer request: {}", err);
+                });
+            },
+            Err(err) => eprintln!("failed to accept connection: {}", err)
+        }
+    }
+    Ok(())
+}
@@ -1,5 +1,5 @@
 use crate::Shell;
-use std::fmt;
+use std::{fmt, path::Path};
 
 pub struct Fg(pub Shell, pub u8);
 impl fmt::Display for Fg {
@@ -43,6 +43,71 @@ impl fmt::Display for Reset {
     }
 }
 
+/// The start of an OSC 8 hyperlink to `url`, or the end of one if it's
+/// empty. The url must already be escaped for the shell.
+pub struct Link<'a>(pub Shell, pub &'a str);
+impl<'a> fmt::Display for Link<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // The parameters before the ; are left empty
+        Osc(self.0, 8, &format!(";{}", self.1)).fmt(f)
+    }
+}
+
+/// An operating system command, like setting the window title. The text
+/// must already be escaped for the shell.
+pub struct Osc<'a>(pub Shell, pub u8, pub &'a str);
+impl<'a> fmt::Display for Osc<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Shell::Bare => write!(f, "\x1b]{};{}\x1b\\", self.1, self.2),
+            Shell::Bash => write!(f, "\\[\\e]{};{}\\e\\\\\\]", self.1, self.2),
+            Shell::Zsh  => write!(f, "%{{\x1b]{};{}\x1b\\%}}", self.1, self.2)
+        }
+    }
+}
+
+/// Percent-encode everything but unreserved characters and slashes
+pub fn percent_encode(string: &str) -> String {
+    let mut output = String::with_capacity(string.len());
+    for byte in string.bytes() {
+        match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => output.push(byte as char),
+            byte => output.push_str(&format!("%{:02X}", byte))
+        }
+    }
+    output
+}
+/// A file:// URL for `path` on this host
+pub fn file_url(host: &str, path: &Path) -> String {
+    format!("file://{}{}", host, percent_encode(&path.to_string_lossy()))
+}
+/// Fill in the `{name}` placeholders of `template` in a single pass, so a
+/// value containing braces is never mistaken for a placeholder. `value`
+/// returns `None` for names it doesn't know, which are kept as is.
+pub fn fill<F>(template: &str, mut value: F) -> String
+    where F: FnMut(&str) -> Option<String>
+{
+    let mut string = String::with_capacity(template.len() + 16);
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        string.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let end = match rest.find('}') {
+            Some(end) => end,
+            None => break
+        };
+        match value(&rest[1..end]) {
+            Some(value) => string.push_str(&value),
+            // Not a placeholder we know, keep it as is
+            None => string.push_str(&rest[..=end])
+        }
+        rest = &rest[end+1..];
+    }
+    string.push_str(rest);
+    string
+}
+
 pub fn root(shell: Shell) -> &'static str {
     match shell {
         Shell::Bare => "$",
@@ -73,3 +138,19 @@ pub fn escape(shell: Shell, string: &mut String) {
     }
     *string = output;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn templates() {
+        let value = |name: &str| match name {
+            "branch" => Some(String::from("{cwd}")),
+            "cwd" => Some(String::from("~/src")),
+            _ => None
+        };
+        assert_eq!(fill("{cwd} ({branch})", value), "~/src ({cwd})");
+        assert_eq!(fill("{other} {cwd", value), "{other} {cwd");
+    }
+}
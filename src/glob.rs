@@ -0,0 +1,61 @@
+/// Match `text` against a shell-style `pattern`, where `*` matches any
+/// sequence of characters and `?` matches any single character.
+pub fn matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    matches_from(&pattern, &text, false)
+}
+/// Like `matches`, but `*` and `?` don't match a `/`. Use `**` to match
+/// across directories.
+pub fn matches_path(pattern: &str, path: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let path: Vec<char> = path.chars().collect();
+    matches_from(&pattern, &path, true)
+}
+
+fn matches_from(pattern: &[char], text: &[char], path: bool) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some('*') => {
+            let any = !path || pattern.get(1) == Some(&'*');
+            let rest = &pattern[if path && any { 2 } else { 1 }..];
+            // Let the * swallow more and more characters until the rest matches
+            for start in 0..=text.len() {
+                if matches_from(rest, &text[start..], path) {
+                    return true;
+                }
+                if start < text.len() && !any && text[start] == '/' {
+                    break;
+                }
+            }
+            false
+        },
+        Some(&c) => match text.first() {
+            Some(&t) if (c == '?' && !(path && t == '/')) || c == t => matches_from(&pattern[1..], &text[1..], path),
+            _ => false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text() {
+        assert!(matches("*github.com*", "git@github.com:owner/repo.git"));
+        assert!(matches("*", ""));
+        assert!(matches("a?c*", "abc/def"));
+        assert!(!matches("*gitlab*", "https://github.com/owner/repo"));
+    }
+
+    #[test]
+    fn paths() {
+        assert!(matches_path("/home/me/*", "/home/me/src"));
+        assert!(!matches_path("/home/me/*", "/home/me/src/proj"));
+        assert!(matches_path("/home/me/**", "/home/me/src/proj"));
+        assert!(!matches_path("/home/me/**", "/home/me"));
+        assert!(matches_path("/home/**/proj", "/home/me/src/proj"));
+        assert!(!matches_path("/home/me?src", "/home/me/src"));
+    }
+}
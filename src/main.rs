@@ -2,14 +2,22 @@
 extern crate clap;
 
 mod cli;
+#[cfg(feature = "git2")]
+mod daemon;
 mod format;
+mod glob;
 mod module;
 mod segments;
 mod theme;
+mod title;
 
 use crate::module::Module;
 use crate::segments::Segment;
 use crate::theme::Theme;
+use std::{
+    path::PathBuf,
+    time::{Duration, Instant}
+};
 
 #[derive(Clone, Copy, Eq, PartialEq)]
 pub enum Shell {
@@ -22,14 +30,33 @@ pub struct Powerline {
     segments: Vec<Segment>,
     theme: Theme,
     shell: Shell,
+    hyperlinks: bool,
 
     #[cfg(feature = "git2")]
     git: Option<git2::Repository>,
+    /// The index of the segment the git module pushed for the branch
+    git_branch: Option<usize>,
     #[cfg(feature = "git2")]
-    git_statuses: Option<Vec<git2::Status>>
+    git_branch_urls: Vec<(String, String)>,
+    #[cfg(feature = "git2")]
+    git_cli: bool,
+    git_config: Option<segments::RepoConfig>,
+    git_hide: Vec<String>,
+    git_ignore_submodules: segments::IgnoreSubmodules,
+    git_jj: segments::JjGit,
+    #[cfg(feature = "git2")]
+    git_indicators: segments::GitIndicators,
+    git_porcelain: Option<segments::git_cli::Porcelain>,
+    #[cfg(feature = "git2")]
+    git_status: Option<segments::GitStatus>,
+    git_work_trees: Vec<segments::WorkTreeMapping>,
+    git_deadline: Option<Instant>,
+    git_timed_out: bool
 }
 
 fn main() {
+    let start = Instant::now();
+
     #[cfg(feature = "flame")]
     flame::start("clap-rs");
 
@@ -38,12 +65,56 @@ fn main() {
     #[cfg(feature = "flame")]
     flame::end("clap-rs");
 
+    if matches.subcommand_matches("daemon").is_some() {
+        #[cfg(feature = "git2")]
+        {
+            if let Err(err) = daemon::run() {
+                eprintln!("daemon: {}", err);
+                std::process::exit(1);
+            }
+            return;
+        }
+        #[cfg(not(feature = "git2"))]
+        {
+            eprintln!("daemon: git support is disabled");
+            std::process::exit(1);
+        }
+    }
+
     #[cfg(feature = "flame")]
     flame::start("parse arguments");
 
-    let cwd_max_depth    = value_t_or_exit!(matches, "cwd-max-depth", u8);
-    let cwd_max_dir_size = value_t_or_exit!(matches, "cwd-max-dir-size", u8);
+    let cwd_options = segments::CwdOptions {
+        max_depth:    value_t_or_exit!(matches, "cwd-max-depth", u8),
+        max_dir_size: value_t_or_exit!(matches, "cwd-max-dir-size", u8),
+        max_length:   value_t_or_exit!(matches, "cwd-max-length", usize),
+        mode: match matches.value_of("cwd-mode").unwrap() {
+            "full"   => segments::CwdMode::Full,
+            "fish"   => segments::CwdMode::Fish,
+            "unique" => segments::CwdMode::Unique,
+            "repo"   => segments::CwdMode::Repo,
+            "plain"  => segments::CwdMode::Plain,
+            _ => unreachable!()
+        },
+        separator: matches.value_of("cwd-separator").unwrap().to_string(),
+        // The output of zsh's `hash -d`, one name=path per line
+        named_dirs: matches.value_of("cwd-named-dirs")
+            .into_iter()
+            .flat_map(str::lines)
+            .filter_map(|line| {
+                let mut parts = line.splitn(2, '=');
+                let name = parts.next()?;
+                let dir = parts.next()?.trim_start_matches('\'').trim_end_matches('\'');
+                Some((name.to_string(), PathBuf::from(dir)))
+            })
+            .collect()
+    };
     let error            = value_t_or_exit!(matches, "error", u8);
+    let git_timeout      = value_t_or_exit!(matches, "git-timeout", u64);
+    #[cfg(feature = "git2")]
+    let git_commit_subject_length = value_t_or_exit!(matches, "git-commit-subject-length", usize);
+    #[cfg(feature = "git2")]
+    let git_diff_max_files = value_t_or_exit!(matches, "git-diff-max-files", usize);
 
     #[cfg(feature = "flame")]
     flame::start("parse theme");
@@ -71,6 +142,40 @@ fn main() {
 
     let time_format = matches.value_of("time_format").unwrap();
 
+    #[cfg(feature = "git2")]
+    let mut git_indicators = segments::GitIndicators::default();
+    #[cfg(feature = "git2")]
+    for indicator in matches.values_of("git-indicators").into_iter().flatten() {
+        match indicator {
+            "remote"    => git_indicators.remote = true,
+            "worktree"  => git_indicators.worktree = true,
+            "submodule" => git_indicators.submodule = true,
+            "shallow"   => git_indicators.shallow = true,
+            "sparse"    => git_indicators.sparse = true,
+            _ => unreachable!()
+        }
+    }
+
+    let git_hide = matches.values_of("git-hide")
+        .into_iter()
+        .flatten()
+        .map(segments::expand_home)
+        .collect();
+    let git_work_trees = matches.values_of("git-work-trees")
+        .into_iter()
+        .flatten()
+        .filter_map(|mapping| {
+            let mut parts = mapping.splitn(2, '=');
+            let work_tree = PathBuf::from(segments::expand_home(parts.next()?));
+            let git_dir = PathBuf::from(segments::expand_home(parts.next()?));
+            Some(segments::WorkTreeMapping {
+                // The current directory we compare with has symlinks resolved
+                work_tree: work_tree.canonicalize().unwrap_or(work_tree),
+                git_dir
+            })
+        })
+        .collect();
+
     #[cfg(feature = "flame")]
     flame::end("parse modules");
 
@@ -89,18 +194,76 @@ fn main() {
             "zsh"  => Shell::Zsh,
             _ => unreachable!()
         },
+        hyperlinks: matches.is_present("hyperlinks"),
 
         #[cfg(feature = "git2")]
         git: None,
+        git_branch: None,
+        #[cfg(feature = "git2")]
+        git_branch_urls: matches.values_of("git-branch-urls")
+            .into_iter()
+            .flatten()
+            .filter_map(|rule| {
+                let mut parts = rule.splitn(2, '=');
+                Some((parts.next()?.to_string(), parts.next()?.to_string()))
+            })
+            .collect(),
+        #[cfg(feature = "git2")]
+        git_cli: matches.is_present("git-cli"),
+        git_config: None,
+        git_hide,
+        git_ignore_submodules: match matches.value_of("git-ignore-submodules").unwrap() {
+            "none"      => segments::IgnoreSubmodules::None,
+            "untracked" => segments::IgnoreSubmodules::Untracked,
+            "dirty"     => segments::IgnoreSubmodules::Dirty,
+            "all"       => segments::IgnoreSubmodules::All,
+            _ => unreachable!()
+        },
+        #[cfg(feature = "git2")]
+        git_indicators,
+        git_jj: match matches.value_of("git-jj").unwrap() {
+            "show"     => segments::JjGit::Show,
+            "annotate" => segments::JjGit::Annotate,
+            "hide"     => segments::JjGit::Hide,
+            _ => unreachable!()
+        },
+        git_porcelain: None,
         #[cfg(feature = "git2")]
-        git_statuses: None
+        git_status: None,
+        git_work_trees,
+        git_deadline: if git_timeout > 0 {
+            Some(start + Duration::from_millis(git_timeout))
+        } else { None },
+        git_timed_out: false
     };
 
     for module in modules {
         match module {
-            Module::Cwd => segments::segment_cwd(&mut p, cwd_max_depth, cwd_max_dir_size),
-            Module::Git => { #[cfg(feature = "git2")] segments::segment_git(&mut p) },
-            Module::GitStage => { #[cfg(feature = "git2")] segments::segment_gitstage(&mut p) },
+            Module::Cwd => segments::segment_cwd(&mut p, &cwd_options),
+            Module::Git => segments::segment_git(&mut p),
+            Module::GitCommit => {
+                #[cfg(feature = "git2")]
+                segments::segment_gitcommit(
+                    &mut p,
+                    matches.value_of("git-commit-format").unwrap(),
+                    git_commit_subject_length
+                )
+            },
+            Module::GitDiff => {
+                #[cfg(feature = "git2")]
+                segments::segment_gitdiff(&mut p, matches.is_present("git-diff-split"), git_diff_max_files)
+            },
+            Module::GitIdentity => {
+                #[cfg(feature = "git2")]
+                segments::segment_gitidentity(&mut p, matches.values_of("git-identity").into_iter().flatten())
+            },
+            Module::GitStage => segments::segment_gitstage(&mut p),
+            Module::GitTag => {
+                #[cfg(feature = "git2")]
+                segments::segment_gittag(&mut p)
+            },
+            Module::Hg => segments::segment_hg(&mut p),
+            Module::Jj => segments::segment_jj(&mut p),
             Module::Host => segments::segment_host(&mut p),
             Module::Jobs => segments::segment_jobs(&mut p),
             Module::NixShell => segments::segment_nix(&mut p),
@@ -108,8 +271,9 @@ fn main() {
             Module::Ps => segments::segment_ps(&mut p),
             Module::Root => segments::segment_root(&mut p, error),
             Module::Ssh => segments::segment_ssh(&mut p),
-            Module::Time => segments::segment_time(&mut p, &time_format),
+            Module::Time => segments::segment_time(&mut p, time_format),
             Module::User => segments::segment_user(&mut p),
+            Module::Vcs => segments::segment_vcs(&mut p),
             Module::LineBreak => segments::segment_linebreak(&mut p),
             Module::VirtualEnv => segments::segment_virtualenv(&mut p),
         }
@@ -120,6 +284,13 @@ fn main() {
     #[cfg(feature = "flame")]
     flame::start("print");
 
+    if let Some(template) = matches.value_of("title") {
+        title::print_title(&mut p, template);
+    }
+    if matches.is_present("osc7") {
+        title::print_cwd(&p);
+    }
+
     if matches.is_present("rtl") {
         let n = p.segments.len();
         for i in 1..n+1 {
@@ -3,7 +3,13 @@ use std::str::FromStr;
 pub const ALL: &[&str] = &[
     "cwd",
     "git",
+    "gitcommit",
+    "gitdiff",
+    "gitidentity",
     "gitstage",
+    "gittag",
+    "hg",
+    "jj",
     "host",
     "jobs",
     "nix-shell",
@@ -13,6 +19,7 @@ pub const ALL: &[&str] = &[
     "ssh",
     "time",
     "user",
+    "vcs",
     "virtualenv",
     "linebreak",
 ];
@@ -21,7 +28,13 @@ pub const ALL: &[&str] = &[
 pub enum Module {
     Cwd,
     Git,
+    GitCommit,
+    GitDiff,
+    GitIdentity,
     GitStage,
+    GitTag,
+    Hg,
+    Jj,
     Host,
     Jobs,
     NixShell,
@@ -31,6 +44,7 @@ pub enum Module {
     Ssh,
     Time,
     User,
+    Vcs,
     VirtualEnv,
     LineBreak,
 }
@@ -41,7 +55,13 @@ impl FromStr for Module {
         match s {
             "cwd"        => Ok(Module::Cwd),
             "git"        => Ok(Module::Git),
+            "gitcommit"  => Ok(Module::GitCommit),
+            "gitdiff"    => Ok(Module::GitDiff),
+            "gitidentity" => Ok(Module::GitIdentity),
             "gitstage"   => Ok(Module::GitStage),
+            "gittag"     => Ok(Module::GitTag),
+            "hg"         => Ok(Module::Hg),
+            "jj"         => Ok(Module::Jj),
             "host"       => Ok(Module::Host),
             "jobs"       => Ok(Module::Jobs),
             "nix-shell"  => Ok(Module::NixShell),
@@ -51,6 +71,7 @@ impl FromStr for Module {
             "ssh"        => Ok(Module::Ssh),
             "time"       => Ok(Module::Time),
             "user"       => Ok(Module::User),
+            "vcs"        => Ok(Module::Vcs),
             "virtualenv" => Ok(Module::VirtualEnv),
             "linebreak"  => Ok(Module::LineBreak),
             _          => Err(())
@@ -0,0 +1,51 @@
+#[cfg(feature = "flame")] use flame;
+use std::{
+    io::Read,
+    process::{Command, Stdio},
+    sync::mpsc,
+    thread,
+    time::Instant
+};
+
+/// Given up on because the deadline passed
+pub struct TimedOut;
+
+/// Run a command and return its output, or `None` if it failed (e.g. because
+/// it isn't installed). It's killed once the deadline passes.
+pub fn output(command: &mut Command, deadline: Option<Instant>) -> Result<Option<String>, TimedOut> {
+    #[cfg(feature = "flame")]
+    let _guard = flame::start_guard("run command");
+
+    let mut child = match command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn() {
+        Ok(child) => child,
+        Err(_) => return Ok(None)
+    };
+
+    // Read in a thread so we can give up on it once the deadline passes
+    let mut stdout = child.stdout.take().unwrap();
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut output = String::new();
+        let _ = tx.send(stdout.read_to_string(&mut output).map(|_| output).ok());
+    });
+
+    let output = match deadline {
+        None => rx.recv().ok().and_then(|output| output),
+        Some(deadline) => match rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok(output) => output,
+            Err(_) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(TimedOut);
+            }
+        }
+    };
+    match child.wait() {
+        Ok(exit) if exit.success() => Ok(output),
+        _ => Ok(None)
+    }
+}
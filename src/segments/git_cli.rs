@@ -0,0 +1,361 @@
+#[cfg(feature = "flame")] use flame;
+use crate::Powerline;
+use super::{command::{self, TimedOut}, segment_git::*, RepoConfig};
+use std::{
+    env,
+    ffi::OsString,
+    path::PathBuf,
+    process::Command,
+    time::{Duration, Instant}
+};
+
+/// The parsed output of `git status --porcelain=v2 --branch`
+pub struct Porcelain {
+    /// The branch name or short commit id, `None` if there are no commits
+    head: Option<String>,
+    ahead_behind: Option<(usize, usize)>,
+    status: GitStatus
+}
+
+/// How long reading HEAD may take once the status ran out of time
+const HEAD_GRACE: Duration = Duration::from_millis(50);
+
+/// The arguments pointing git at a repository from --git-work-trees, if any
+fn repo_args(work_trees: &[WorkTreeMapping]) -> Vec<OsString> {
+    match mapped_repository(work_trees) {
+        Some(mapping) => {
+            let mut git_dir = OsString::from("--git-dir=");
+            git_dir.push(&mapping.git_dir);
+            let mut work_tree = OsString::from("--work-tree=");
+            work_tree.push(&mapping.work_tree);
+            vec![git_dir, work_tree]
+        },
+        None => Vec::new()
+    }
+}
+
+/// Run git in the current directory and return its output, or `None` if it
+/// failed (e.g. because we're not in a repository).
+fn git(repo: &[OsString], args: &[&str], deadline: Option<Instant>) -> Result<Option<String>, TimedOut> {
+    #[cfg(feature = "flame")]
+    let _guard = flame::start_guard("git command");
+
+    command::output(Command::new("git").args(repo).args(args), deadline)
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match &*value.to_lowercase() {
+        "true" | "yes" | "on" | "1" | "" => Some(true),
+        "false" | "no" | "off" | "0" => Some(false),
+        _ => None
+    }
+}
+
+fn config_if_none(
+    repo: &[OsString],
+    config: &mut Option<RepoConfig>,
+    deadline: Option<Instant>,
+    timed_out: &mut bool
+) -> bool {
+    #[cfg(feature = "flame")]
+    let _guard = flame::start_guard("git config");
+
+    if config.is_none() {
+        let output = match git(repo, &["config", "-z", "--get-regexp", r"^powerline-rs\."], deadline) {
+            Ok(output) => output,
+            Err(TimedOut) => {
+                *timed_out = true;
+                return false;
+            }
+        };
+        *config = Some(parse_config(output.as_deref().unwrap_or("")));
+    }
+    true
+}
+fn parse_config(output: &str) -> RepoConfig {
+    let mut config = RepoConfig::default();
+    // -z separates the key from the value with a newline, and each entry
+    // with a nul byte
+    for entry in output.split('\0') {
+        let mut parts = entry.splitn(2, '\n');
+        let key = parts.next().unwrap_or("");
+        if let Some(value) = parse_bool(parts.next().unwrap_or("")) {
+            config.set(key, value);
+        }
+    }
+    config
+}
+/// Whether git could find a repository from here. Checking ourselves saves
+/// spawning git on every prompt outside of one.
+fn in_repository(repo: &[OsString]) -> bool {
+    if !repo.is_empty() || env::var_os("GIT_DIR").is_some() {
+        return true;
+    }
+    env::current_dir()
+        .map(|cwd| cwd.ancestors().any(|dir| dir.join(".git").exists()))
+        .unwrap_or(false)
+}
+
+/// The branch HEAD points to, or the short commit id if detached. This is
+/// all that's needed when the status isn't.
+fn head(repo: &[OsString], deadline: Option<Instant>) -> Option<String> {
+    let head = match git(repo, &["symbolic-ref", "--short", "-q", "HEAD"], deadline) {
+        Ok(Some(branch_name)) => Some(branch_name),
+        // Detached head
+        Ok(None) => git(repo, &["rev-parse", "--short", "-q", "HEAD"], deadline).ok().and_then(|id| id),
+        Err(TimedOut) => None
+    };
+    head.map(|head| head.trim_end().to_string())
+}
+
+fn parse_porcelain(output: &str) -> Porcelain {
+    #[cfg(feature = "flame")]
+    let _guard = flame::start_guard("parse porcelain");
+
+    let mut oid = None;
+    let mut head = None;
+    let mut porcelain = Porcelain {
+        head: None,
+        ahead_behind: None,
+        status: GitStatus::default()
+    };
+
+    for line in output.lines() {
+        let mut words = line.splitn(3, ' ');
+        match (words.next(), words.next()) {
+            (Some("#"), Some("branch.oid")) => oid = words.next().filter(|&oid| oid != "(initial)"),
+            (Some("#"), Some("branch.head")) => head = words.next().filter(|&head| head != "(detached)"),
+            (Some("#"), Some("branch.ab")) => {
+                let mut counts = words.next().unwrap_or("").split(' ')
+                    .map(|count| count.trim_start_matches(&['+', '-'][..]).parse().ok());
+                if let (Some(Some(ahead)), Some(Some(behind))) = (counts.next(), counts.next()) {
+                    porcelain.ahead_behind = Some((ahead, behind));
+                }
+            },
+            (Some("1"), Some(xy)) | (Some("2"), Some(xy)) => {
+                porcelain.status.changes += 1;
+
+                // Submodules are counted separately, like in the libgit2 backend
+                if words.next().map(|sub| sub.starts_with('S')).unwrap_or(false) {
+                    porcelain.status.submodules += 1;
+                    continue;
+                }
+
+                let mut xy = xy.chars();
+                let (x, y) = (xy.next().unwrap_or('.'), xy.next().unwrap_or('.'));
+                if x != '.' {
+                    porcelain.status.staged += 1;
+                }
+                if y == 'M' || y == 'T' || y == 'D' {
+                    porcelain.status.notstaged += 1;
+                }
+            },
+            (Some("u"), Some(_)) => {
+                porcelain.status.changes += 1;
+                porcelain.status.conflicted += 1;
+            },
+            (Some("?"), Some(_)) => {
+                porcelain.status.changes += 1;
+                porcelain.status.untracked += 1;
+            },
+            _ => ()
+        }
+    }
+
+    porcelain.head = match (head, oid) {
+        (Some(head), _) if oid.is_some() => Some(head.to_string()),
+        // Detached head
+        (None, Some(oid)) => Some(oid.chars().take(7).collect()),
+        _ => None
+    };
+    porcelain
+}
+
+fn porcelain_if_none(p: &mut Powerline) -> bool {
+    #[cfg(feature = "flame")]
+    let _guard = flame::start_guard("git status");
+
+    if p.git_timed_out || hidden_here(&p.git_hide, &p.git_work_trees) {
+        return false;
+    }
+    let repo = repo_args(&p.git_work_trees);
+    if p.git_config.is_none() && !in_repository(&repo) {
+        return false;
+    }
+    if !config_if_none(&repo, &mut p.git_config, p.git_deadline, &mut p.git_timed_out) {
+        return false;
+    }
+    let config = p.git_config.as_ref().unwrap();
+    if config.hide {
+        return false;
+    }
+
+    if p.git_porcelain.is_none() {
+        let ignore_submodules = format!("--ignore-submodules={}", p.git_ignore_submodules.as_str());
+        let mut args = vec!["status", "--porcelain=v2", "--branch", &ignore_submodules];
+        if !config.untracked || !config.status {
+            args.push("--untracked-files=no");
+        }
+        if !config.status {
+            // Only the branch is wanted, so exclude every file
+            args.extend_from_slice(&["--", ":(top,exclude)*"]);
+        }
+
+        p.git_porcelain = match git(&repo, &args, p.git_deadline) {
+            Ok(output) => output.map(|output| parse_porcelain(&output)),
+            Err(TimedOut) => {
+                p.git_timed_out = true;
+                None
+            }
+        };
+    }
+    p.git_porcelain.is_some()
+}
+
+pub fn segment_git(p: &mut Powerline) {
+    #[cfg(feature = "flame")]
+    let _guard = flame::start_guard("segment git");
+
+    if !porcelain_if_none(p) {
+        if p.git_timed_out {
+            // Ran out of time. Reading HEAD alone doesn't scan the working
+            // tree, so that should still be quick, but don't wait forever.
+            let repo = repo_args(&p.git_work_trees);
+            if let Some(branch_name) = head(&repo, Some(Instant::now() + HEAD_GRACE)) {
+                push_branch_unknown(p, branch_name);
+            }
+        }
+        return;
+    }
+    let porcelain = p.git_porcelain.as_ref().unwrap();
+    let status = if p.git_config.as_ref().unwrap().status { Some(porcelain.status) } else { None };
+    let ahead_behind = porcelain.ahead_behind;
+
+    match porcelain.head.clone() {
+        Some(branch_name) => push_branch(p, branch_name, status.as_ref(), None),
+        None => {
+            push_big_bang(p);
+            return;
+        }
+    }
+
+    if let Some((ahead, behind)) = ahead_behind {
+        push_ahead_behind(p, ahead, behind);
+    }
+}
+pub fn branch_name(p: &mut Powerline) -> Option<String> {
+    if let Some(ref porcelain) = p.git_porcelain {
+        return porcelain.head.clone();
+    }
+    if hidden_here(&p.git_hide, &p.git_work_trees) {
+        return None;
+    }
+    let repo = repo_args(&p.git_work_trees);
+    if p.git_config.is_none() && !in_repository(&repo) {
+        return None;
+    }
+    if config_if_none(&repo, &mut p.git_config, p.git_deadline, &mut p.git_timed_out)
+            && p.git_config.as_ref().unwrap().hide {
+        return None;
+    }
+    // Unlike `git status`, reading HEAD doesn't scan the working tree
+    let deadline = if p.git_timed_out {
+        Some(Instant::now() + HEAD_GRACE)
+    } else { p.git_deadline };
+    head(&repo, deadline)
+}
+pub fn work_tree(p: &mut Powerline) -> Option<PathBuf> {
+    if hidden_here(&p.git_hide, &p.git_work_trees) {
+        return None;
+    }
+    let repo = repo_args(&p.git_work_trees);
+    if p.git_config.is_none() && !in_repository(&repo) {
+        return None;
+    }
+    if !config_if_none(&repo, &mut p.git_config, p.git_deadline, &mut p.git_timed_out)
+            || p.git_config.as_ref().unwrap().hide {
+        return None;
+    }
+    // Fails in a bare repository or inside the git directory
+    match git(&repo, &["rev-parse", "--show-toplevel"], p.git_deadline) {
+        Ok(root) => root.map(|root| PathBuf::from(root.trim_end_matches('\n'))),
+        Err(TimedOut) => {
+            p.git_timed_out = true;
+            None
+        }
+    }
+}
+pub fn segment_gitstage(p: &mut Powerline) {
+    #[cfg(feature = "flame")]
+    let _guard = flame::start_guard("segment gitstage");
+
+    if !porcelain_if_none(p) || !p.git_config.as_ref().unwrap().status {
+        return;
+    }
+    let status = p.git_porcelain.as_ref().unwrap().status;
+    push_stage(p, &status);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bools() {
+        assert_eq!(parse_bool("TRUE"), Some(true));
+        assert_eq!(parse_bool(""), Some(true));
+        assert_eq!(parse_bool("off"), Some(false));
+        assert_eq!(parse_bool("maybe"), None);
+    }
+
+    #[test]
+    fn config() {
+        let config = parse_config("powerline-rs.untracked\nfalse\0powerline-rs.hide\nyes\0powerline-rs.status\nnope\0");
+        assert!(config.hide);
+        assert!(config.status);
+        assert!(!config.untracked);
+
+        let config = parse_config("");
+        assert!(!config.hide && config.status && config.untracked);
+    }
+
+    #[test]
+    fn branch_headers() {
+        let porcelain = parse_porcelain("# branch.oid 2bcade5f00d\n# branch.head main\n# branch.upstream origin/main\n# branch.ab +2 -13\n");
+        assert_eq!(porcelain.head.as_deref(), Some("main"));
+        assert_eq!(porcelain.ahead_behind, Some((2, 13)));
+        assert!(porcelain.status.is_clean());
+
+        // Detached
+        let porcelain = parse_porcelain("# branch.oid 2bcade5f00d\n# branch.head (detached)\n");
+        assert_eq!(porcelain.head.as_deref(), Some("2bcade5"));
+        assert_eq!(porcelain.ahead_behind, None);
+
+        // No commits yet
+        let porcelain = parse_porcelain("# branch.oid (initial)\n# branch.head main\n");
+        assert_eq!(porcelain.head, None);
+    }
+
+    #[test]
+    fn entries() {
+        let porcelain = parse_porcelain(concat!(
+            "# branch.oid 2bcade5f00d\n",
+            "# branch.head main\n",
+            "1 M. N... 100644 100644 100644 aaa bbb staged.txt\n",
+            "1 .M N... 100644 100644 100644 aaa aaa notstaged.txt\n",
+            "1 MD N... 100644 100644 000000 aaa bbb both.txt\n",
+            "2 R. N... 100644 100644 100644 aaa aaa R100 new name.txt\told name.txt\n",
+            "1 .M SC.. 160000 160000 160000 aaa aaa submodule\n",
+            "u UU N... 100644 100644 100644 100644 aaa bbb ccc conflict.txt\n",
+            "? untracked one.txt\n",
+            "? untracked two.txt\n"
+        ));
+        let status = porcelain.status;
+        assert_eq!(status.changes, 8);
+        assert_eq!(status.staged, 3);
+        assert_eq!(status.notstaged, 2);
+        assert_eq!(status.submodules, 1);
+        assert_eq!(status.conflicted, 1);
+        assert_eq!(status.untracked, 2);
+    }
+}
@@ -0,0 +1,487 @@
+#[cfg(feature = "flame")] use flame;
+use crate::{daemon, format, glob, Powerline};
+use git2::{
+    BranchType, ObjectType, Repository, Status, StatusOptions, StatusShow,
+    SubmoduleIgnore, SubmoduleStatus
+};
+use super::{command::TimedOut, segment_git::*, IgnoreSubmodules, RepoConfig};
+use std::{
+    env,
+    path::{Path, PathBuf},
+    sync::mpsc,
+    thread,
+    time::Instant
+};
+
+fn load_config(git: &Repository) -> RepoConfig {
+    let mut repo_config = RepoConfig::default();
+    if let Ok(config) = git.config() {
+        for key in &["powerline-rs.hide", "powerline-rs.status", "powerline-rs.untracked"] {
+            if let Ok(value) = config.get_bool(key) {
+                repo_config.set(key, value);
+            }
+        }
+    }
+    repo_config
+}
+
+fn open(work_trees: &[WorkTreeMapping]) -> Option<Repository> {
+    match mapped_repository(work_trees) {
+        Some(mapping) => {
+            let git = Repository::open(&mapping.git_dir).ok()?;
+            git.set_workdir(&mapping.work_tree, false).ok()?;
+            Some(git)
+        },
+        // libgit2 refuses to open anything while $GIT_WORK_TREE is set, so
+        // that one is applied by hand
+        None => match env::var_os("GIT_WORK_TREE") {
+            Some(work_tree) => {
+                let git = match env::var_os("GIT_DIR") {
+                    Some(git_dir) => Repository::open(git_dir),
+                    None => Repository::discover(".")
+                }.ok()?;
+                git.set_workdir(Path::new(&work_tree), false).ok()?;
+                Some(git)
+            },
+            // Respects $GIT_DIR and $GIT_CEILING_DIRECTORIES like git itself
+            None => Repository::open_from_env().ok()
+        }
+    }
+}
+pub fn discover_if_none(p: &mut Powerline) -> bool {
+    #[cfg(feature = "flame")]
+    let _guard = flame::start_guard("git discover");
+
+    if p.git.is_none() {
+        if hidden_here(&p.git_hide, &p.git_work_trees) {
+            return false;
+        }
+        p.git = open(&p.git_work_trees);
+        p.git_config = p.git.as_ref().map(load_config);
+    }
+    p.git_config.as_ref().map(|config| !config.hide).unwrap_or(false)
+}
+/// Whether the daemon, which opens a repository from its git directory alone,
+/// ends up with the same work tree as we did
+fn daemon_usable(git: &Repository) -> bool {
+    env::var_os("GIT_WORK_TREE").is_none()
+        && !git.config().and_then(|config| config.get_bool("core.bare")).unwrap_or(false)
+}
+pub fn collect_statuses(git: &Repository, untracked: bool) -> Option<Vec<Status>> {
+    git.statuses(Some(
+            StatusOptions::new()
+                .show(StatusShow::IndexAndWorkdir)
+                .include_untracked(untracked)
+                // Submodules are counted separately, see count_submodules
+                .exclude_submodules(true)
+                .renames_from_rewrites(true)
+                .renames_head_to_index(true)
+        ))
+        .ok()
+        .map(|statuses|
+            statuses.iter()
+            .map(|entry| entry.status())
+            .collect())
+}
+fn count_statuses(statuses: &[Status]) -> GitStatus {
+    #[cfg(feature = "flame")]
+    let _guard = flame::start_guard("counting");
+
+    let mut count = GitStatus {
+        changes: statuses.len(),
+        ..GitStatus::default()
+    };
+
+    for status in statuses {
+        if status.contains(Status::INDEX_NEW)
+            || status.contains(Status::INDEX_MODIFIED)
+            || status.contains(Status::INDEX_TYPECHANGE)
+            || status.contains(Status::INDEX_RENAMED)
+            || status.contains(Status::INDEX_DELETED) {
+            count.staged += 1;
+        }
+        if status.contains(Status::WT_MODIFIED)
+            || status.contains(Status::WT_TYPECHANGE)
+            || status.contains(Status::WT_DELETED) {
+            count.notstaged += 1;
+        }
+        if status.contains(Status::WT_NEW) {
+            count.untracked += 1;
+        }
+        if status.contains(Status::CONFLICTED) {
+            count.conflicted += 1;
+        }
+    }
+
+    count
+}
+/// Run `f` on the repository, giving up once the deadline passes. libgit2
+/// can't be interrupted, so it runs on a separate handle in a thread that is
+/// simply abandoned if it's too slow. It dies with the process.
+pub fn with_deadline<T, F>(git: &Repository, deadline: Option<Instant>, f: F) -> Result<Option<T>, TimedOut>
+where
+    T: Send + 'static,
+    F: FnOnce(&Repository) -> Option<T> + Send + 'static
+{
+    let deadline = match deadline {
+        Some(deadline) => deadline,
+        None => return Ok(f(git))
+    };
+    let path = git.path().to_path_buf();
+    let workdir = git.workdir().map(Path::to_path_buf);
+    let (tx, rx) = mpsc::channel();
+
+    thread::spawn(move || {
+        let result = Repository::open(&path).ok().and_then(|git| {
+            // Opening by the git directory loses a work tree that came from a
+            // mapping or the environment
+            if let Some(workdir) = workdir {
+                git.set_workdir(&workdir, false).ok()?;
+            }
+            f(&git)
+        });
+        let _ = tx.send(result);
+    });
+
+    rx.recv_timeout(deadline.saturating_duration_since(Instant::now())).map_err(|_| TimedOut)
+}
+/// Count the submodules with changes, or `None` if the deadline passed
+/// before all of them were looked at
+pub fn count_submodules(git: &Repository, ignore: IgnoreSubmodules, deadline: Option<Instant>) -> Option<usize> {
+    #[cfg(feature = "flame")]
+    let _guard = flame::start_guard("count submodules");
+
+    if ignore == IgnoreSubmodules::All {
+        return Some(0);
+    }
+    let ignore = || match ignore {
+        IgnoreSubmodules::None      => SubmoduleIgnore::None,
+        IgnoreSubmodules::Untracked => SubmoduleIgnore::Untracked,
+        IgnoreSubmodules::Dirty     => SubmoduleIgnore::Dirty,
+        IgnoreSubmodules::All       => SubmoduleIgnore::All
+    };
+    // Where the submodule was found doesn't say anything about its state
+    let location = SubmoduleStatus::IN_HEAD | SubmoduleStatus::IN_INDEX
+        | SubmoduleStatus::IN_CONFIG | SubmoduleStatus::IN_WD
+        | SubmoduleStatus::WD_UNINITIALIZED;
+
+    let submodules = match git.submodules() {
+        Ok(submodules) => submodules,
+        Err(_) => return Some(0)
+    };
+    let mut count = 0;
+    for name in submodules.iter().filter_map(|submodule| submodule.name()) {
+        // Each one is a full status scan of its own
+        if deadline.map(|deadline| Instant::now() >= deadline).unwrap_or(false) {
+            return None;
+        }
+        if let Ok(status) = git.submodule_status(name, ignore()) {
+            if !(status - location).is_empty() {
+                count += 1;
+            }
+        }
+    }
+    Some(count)
+}
+fn status_if_none(
+    git: &Repository,
+    config: &RepoConfig,
+    ignore_submodules: IgnoreSubmodules,
+    deadline: Option<Instant>,
+    status: &mut Option<GitStatus>,
+    timed_out: &mut bool
+) -> bool {
+    #[cfg(feature = "flame")]
+    let _guard = flame::start_guard("git status");
+
+    if *timed_out {
+        return false;
+    }
+    if status.is_none() {
+        let reply = if daemon_usable(git) {
+            daemon::query(git.path(), config.untracked, ignore_submodules, deadline)
+        } else { daemon::Reply::NotRunning };
+        let statuses = match reply {
+            daemon::Reply::NotRunning => {
+                let untracked = config.untracked;
+                match with_deadline(git, deadline, move |git| collect_statuses(git, untracked)) {
+                    Ok(Some(statuses)) => match count_submodules(git, ignore_submodules, deadline) {
+                        Some(submodules) => Some((statuses, submodules)),
+                        None => {
+                            *timed_out = true;
+                            None
+                        }
+                    },
+                    Ok(None) => None,
+                    Err(TimedOut) => {
+                        *timed_out = true;
+                        None
+                    }
+                }
+            },
+            daemon::Reply::TimedOut => {
+                *timed_out = true;
+                None
+            },
+            daemon::Reply::Statuses(statuses) => statuses
+        };
+        *status = statuses.map(|(statuses, submodules)| {
+            let mut status = count_statuses(&statuses);
+            status.submodules = submodules;
+            status.changes += submodules;
+            status
+        });
+        status.is_some()
+    } else { true }
+}
+
+fn superproject_name(git: &Repository) -> Option<String> {
+    #[cfg(feature = "flame")]
+    let _guard = flame::start_guard("search superproject");
+
+    let workdir = git.workdir()?;
+    let superproject = Repository::discover(workdir.parent()?).ok()?;
+    let superdir = superproject.workdir()?;
+    let path = workdir.strip_prefix(superdir).ok()?;
+
+    // Make sure it's actually a submodule and not just a nested repository
+    superproject.find_submodule(path.to_str()?).ok()?;
+    superdir.file_name().map(|name| name.to_string_lossy().into_owned())
+}
+/// The URL of the remote the branch tracks, or else of origin or the first
+/// remote there is
+pub fn remote_url(git: &Repository, branch_name: Option<&str>) -> Option<String> {
+    let tracked = branch_name.and_then(|branch_name| {
+        let config = git.config().ok()?;
+        let name = config.get_string(&format!("branch.{}.remote", branch_name)).ok()?;
+        git.find_remote(&name).ok()
+    });
+    let remote = tracked
+        .or_else(|| git.find_remote("origin").ok())
+        .or_else(|| {
+            let remotes = git.remotes().ok()?;
+            let name = remotes.get(0)?;
+            git.find_remote(name).ok()
+        })?;
+    remote.url().map(String::from)
+}
+/// Web pages of branches on known hosts, checked after --git-branch-urls
+const BRANCH_URLS: &[(&str, &str)] = &[
+    ("*github.com*", "https://{host}/{repo}/tree/{branch}"),
+    ("*gitlab.com*", "https://{host}/{repo}/-/tree/{branch}"),
+    ("*bitbucket.org*", "https://{host}/{repo}/branch/{branch}")
+];
+
+/// Split a remote URL like `git@host:owner/repo.git` or
+/// `https://host/owner/repo` into the host and repository path
+fn parse_remote_url(url: &str) -> Option<(&str, &str)> {
+    let (url, scp) = match url.find("://") {
+        Some(i) => (&url[i + 3..], false),
+        None => (url, true)
+    };
+    let url = match url.find('@') {
+        Some(i) if url[..i].find('/').is_none() => &url[i + 1..],
+        _ => url
+    };
+    let end = url.find(&['/', ':'][..])?;
+    let (host, mut rest) = (&url[..end], &url[end + 1..]);
+    if !scp && url[end..].starts_with(':') {
+        // A port, like ssh://host:22/owner/repo
+        rest = &rest[rest.find('/')? + 1..];
+    }
+    let repo = rest.trim_end_matches('/');
+    let repo = repo.trim_end_matches(".git");
+    if host.is_empty() || repo.is_empty() { None } else { Some((host, repo)) }
+}
+/// The web page of the branch on the remote it tracks
+fn branch_url(git: &Repository, branch_name: &str, rules: &[(String, String)]) -> Option<String> {
+    let url = remote_url(git, Some(branch_name))?;
+    let (host, repo) = parse_remote_url(&url)?;
+    let template = rules.iter()
+        .map(|(pattern, template)| (&**pattern, &**template))
+        .chain(BRANCH_URLS.iter().cloned())
+        .find(|(pattern, _)| glob::matches(pattern, &url))
+        .map(|(_, template)| template)?;
+
+    Some(format::fill(template, |name| match name {
+        "host" => Some(host.to_string()),
+        "repo" => Some(repo.to_string()),
+        "branch" => Some(format::percent_encode(branch_name)),
+        _ => None
+    }))
+}
+/// Push the worktree and submodule segments, and add the remote icon and the
+/// shallow and sparse indicators to the branch name
+fn push_indicators(p: &mut Powerline, branch_name: &mut String) {
+    let git = p.git.as_ref().unwrap();
+
+    if p.git_indicators.remote {
+        let icon = remote_url(git, Some(branch_name)).and_then(|url| p.theme.git_remote_icons.iter()
+            .find(|(pattern, _)| glob::matches(pattern, &url))
+            .map(|&(_, icon)| icon));
+        if let Some(icon) = icon {
+            branch_name.insert(0, ' ');
+            branch_name.insert(0, icon);
+        }
+    }
+
+    let superproject = if p.git_indicators.submodule { superproject_name(git) } else { None };
+    let worktree = if p.git_indicators.worktree && git.is_worktree() {
+        git.path().file_name().map(|name| name.to_string_lossy().into_owned())
+    } else { None };
+    let shallow = p.git_indicators.shallow && git.is_shallow();
+    let sparse = p.git_indicators.sparse && git.config()
+        .and_then(|config| config.get_bool("core.sparseCheckout"))
+        .unwrap_or(false);
+
+    if let Some(superproject) = superproject {
+        push_submodule(p, &superproject);
+    }
+    if let Some(worktree) = worktree {
+        push_worktree(p, &worktree);
+    }
+    if shallow || sparse {
+        branch_name.push(' ');
+        if shallow {
+            branch_name.push(p.theme.git_shallow_char);
+        }
+        if sparse {
+            branch_name.push(p.theme.git_sparse_char);
+        }
+    }
+}
+
+pub fn segment_git(p: &mut Powerline) {
+    #[cfg(feature = "flame")]
+    let _guard = flame::start_guard("segment git");
+
+    if !discover_if_none(p) {
+        return;
+    }
+    let git = p.git.as_ref().unwrap();
+
+    #[cfg(feature = "flame")]
+    flame::start("iter branches");
+
+    let branches = git.branches(Some(BranchType::Local));
+    if branches.is_err() {
+        return;
+    }
+
+    let mut branch_name = None;
+    let mut local    = None;
+    let mut upstream = None;
+
+    for (branch, _) in branches.unwrap().flatten() {
+        if branch.is_head() {
+            local    = branch.get().target();
+            upstream = branch.upstream().ok().and_then(|b| b.get().target());
+
+            if let Ok(Some(name)) = branch.name() {
+                branch_name = Some(name.to_string());
+                break;
+            }
+        }
+    }
+
+    #[cfg(feature = "flame")]
+    flame::end("iter branches");
+
+    if branch_name.is_none() {
+        #[cfg(feature = "flame")]
+        let _guard = flame::start_guard("search head");
+
+        // Could be a detached head
+        let unborn = match git.head() {
+            Ok(head) => {
+                if let Some(target) = head.target() {
+                    branch_name = git.find_object(target, Some(ObjectType::Any))
+                                .ok()
+                                .and_then(|obj| obj.short_id().ok())
+                                .and_then(|buf| buf.as_str()
+                                                    .map(|s| s.to_string()))
+                }
+                false
+            },
+            Err(_) => true
+        };
+        if unborn {
+            push_big_bang(p);
+            return;
+        }
+    }
+
+    let mut branch_name = branch_name.unwrap();
+    // Only a branch (not a detached HEAD) has a page
+    let url = if p.hyperlinks && local.is_some() {
+        branch_url(p.git.as_ref().unwrap(), &branch_name, &p.git_branch_urls)
+    } else { None };
+    let start = p.segments.len();
+    push_indicators(p, &mut branch_name);
+
+    let git = p.git.as_ref().unwrap();
+    let config = p.git_config.as_ref().unwrap();
+    if config.status
+            && !status_if_none(git, config, p.git_ignore_submodules, p.git_deadline, &mut p.git_status, &mut p.git_timed_out) {
+        if p.git_timed_out {
+            // Ran out of time. Show the branch but don't risk stalling
+            // the shell any further by checking the remote.
+            push_branch_unknown(p, branch_name);
+        } else {
+            // Don't leave the indicators without a branch to belong to
+            p.segments.truncate(start);
+        }
+        return;
+    }
+    let status = if config.status { p.git_status } else { None };
+    push_branch(p, branch_name, status.as_ref(), url);
+
+    #[cfg(feature = "flame")]
+    let _guard = flame::start_guard("checking remotes");
+
+    let git = p.git.as_ref().unwrap();
+    if let Some(local) = local {
+        if let Some(upstream) = upstream {
+            if let Ok((ahead, behind)) = git.graph_ahead_behind(local, upstream) {
+                push_ahead_behind(p, ahead, behind);
+            }
+        }
+    }
+}
+pub fn branch_name(p: &mut Powerline) -> Option<String> {
+    if !discover_if_none(p) {
+        return None;
+    }
+    let git = p.git.as_ref().unwrap();
+    let head = git.head().ok()?;
+    if head.is_branch() {
+        head.shorthand().map(String::from)
+    } else {
+        let id = head.peel_to_commit().ok()?.as_object().short_id().ok()?;
+        id.as_str().map(String::from)
+    }
+}
+pub fn work_tree(p: &mut Powerline) -> Option<PathBuf> {
+    if !discover_if_none(p) {
+        return None;
+    }
+    // Bare
+    p.git.as_ref().unwrap().workdir().map(Path::to_path_buf)
+}
+pub fn segment_gitstage(p: &mut Powerline) {
+    #[cfg(feature = "flame")]
+    let _guard = flame::start_guard("segment gitstage");
+
+    if !discover_if_none(p) {
+        return;
+    }
+    let git = p.git.as_ref().unwrap();
+    let config = p.git_config.as_ref().unwrap();
+
+    if !config.status
+            || !status_if_none(git, config, p.git_ignore_submodules, p.git_deadline, &mut p.git_status, &mut p.git_timed_out) {
+        return;
+    }
+    let status = p.git_status.unwrap();
+    push_stage(p, &status);
+}
@@ -1,5 +1,10 @@
+pub mod command;
+pub mod git_cli;
 pub mod segment_cwd;
+pub mod segment_git;
+pub mod segment_hg;
 pub mod segment_host;
+pub mod segment_jj;
 pub mod segment_jobs;
 pub mod segment_nix;
 pub mod segment_perms;
@@ -8,11 +13,15 @@ pub mod segment_root;
 pub mod segment_ssh;
 pub mod segment_time;
 pub mod segment_user;
+pub mod segment_vcs;
 pub mod segment_virtualenv;
 pub mod segment_linebreak;
 
 pub use self::segment_cwd::*;
+pub use self::segment_git::*;
+pub use self::segment_hg::*;
 pub use self::segment_host::*;
+pub use self::segment_jj::*;
 pub use self::segment_jobs::*;
 pub use self::segment_nix::*;
 pub use self::segment_perms::*;
@@ -21,11 +30,19 @@ pub use self::segment_root::*;
 pub use self::segment_ssh::*;
 pub use self::segment_time::*;
 pub use self::segment_user::*;
+pub use self::segment_vcs::*;
 pub use self::segment_virtualenv::*;
 pub use self::segment_linebreak::*;
 
-#[cfg(feature = "git2")] pub mod segment_git;
-#[cfg(feature = "git2")] pub use self::segment_git::*;
+#[cfg(feature = "git2")] pub mod git_libgit2;
+#[cfg(feature = "git2")] pub mod segment_gitcommit;
+#[cfg(feature = "git2")] pub mod segment_gitdiff;
+#[cfg(feature = "git2")] pub mod segment_gitidentity;
+#[cfg(feature = "git2")] pub mod segment_gittag;
+#[cfg(feature = "git2")] pub use self::segment_gitcommit::*;
+#[cfg(feature = "git2")] pub use self::segment_gitdiff::*;
+#[cfg(feature = "git2")] pub use self::segment_gitidentity::*;
+#[cfg(feature = "git2")] pub use self::segment_gittag::*;
 
 
 use crate::Shell;
@@ -43,7 +60,8 @@ pub struct Segment {
     no_space_after: bool,
 
     escaped: bool,
-    text: Cow<'static, str>
+    text: Cow<'static, str>,
+    link: Option<String>
 }
 impl Segment {
     pub fn new<S>(bg: u8, fg: u8, text: S) -> Self
@@ -59,7 +77,8 @@ impl Segment {
             no_space_after: false,
 
             escaped: false,
-            text:  text.into()
+            text:  text.into(),
+            link: None
         }
     }
     pub fn dont_escape(mut self) -> Self {
@@ -85,7 +104,17 @@ impl Segment {
         self.no_space_after = true;
         self
     }
+    pub fn prepend(&mut self, text: &str) {
+        self.text.to_mut().insert_str(0, text);
+    }
+    pub fn with_link(mut self, url: String) -> Self {
+        self.link = Some(url);
+        self
+    }
     pub fn escape(&mut self, shell: Shell) {
+        if let Some(ref mut link) = self.link {
+            escape(shell, link);
+        }
         if self.escaped {
             return;
         }
@@ -93,11 +122,18 @@ impl Segment {
         self.escaped = true;
     }
     pub fn print(&self, next: Option<&Segment>, shell: Shell, theme: &Theme) {
-        print!("{}{}{} {}", self.before, Fg(shell, self.fg), Bg(shell, self.bg), self.text);
+        print!("{}{}{}", self.before, Fg(shell, self.fg), Bg(shell, self.bg));
+        if let Some(ref link) = self.link {
+            print!("{}", Link(shell, link));
+        }
+        print!(" {}", self.text);
 
         if !self.no_space_after {
             print!(" ")
         }
+        if self.link.is_some() {
+            print!("{}", Link(shell, ""));
+        }
         match next {
             Some(next) if next.is_conditional() => {},
             Some(next) if next.bg == self.bg => print!("{}", Fg(shell, theme.separator_fg)),
@@ -118,11 +154,18 @@ impl Segment {
             Some(next) => print!("{}{}",  Fg(shell, self.bg), Bg(shell, next.bg)),
             None       => print!("{}", Fg(shell, self.bg))
         }
-        print!("{}{} {}", Fg(shell, self.fg), Bg(shell, self.bg), self.text);
+        print!("{}{}", Fg(shell, self.fg), Bg(shell, self.bg));
+        if let Some(ref link) = self.link {
+            print!("{}", Link(shell, link));
+        }
+        print!(" {}", self.text);
 
         if !self.no_space_after {
             print!(" ")
         }
+        if self.link.is_some() {
+            print!("{}", Link(shell, ""));
+        }
         print!("{}{}{}", Reset(shell, false), Reset(shell, true), self.before);
     }
 }
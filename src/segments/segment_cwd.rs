@@ -1,24 +1,179 @@
-use crate::{Powerline, Segment};
+use crate::{format::file_url, glob, Powerline, Segment};
+use super::{expand_home, hostname};
 use std::{
     borrow::Cow,
     env,
     ffi::OsStr,
-    path::PathBuf
+    fs,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf}
 };
 
-pub fn segment_cwd(p: &mut Powerline, cwd_max_depth: u8, cwd_max_dir_size: u8) {
-    let mut path = env::current_dir().unwrap_or_else(|_| PathBuf::from("error"));
-    if let Some(home) = dirs::home_dir() {
-        let mut new_path = None;
-        if let Ok(new) = path.strip_prefix(&home) {
-            p.segments.push(Segment::new(p.theme.home_bg, p.theme.home_fg, "~"));
-            // TODO: NLL: path = new.to_path_buf();
-            new_path = Some(new.to_path_buf());
+/// How many entries of a directory are looked at to find a unique prefix,
+/// so huge directories don't slow down the prompt
+const UNIQUE_MAX_ENTRIES: usize = 1000;
+
+/// How the directories leading up to the current one are shown
+#[derive(Clone, Copy, Eq, PartialEq)]
+pub enum CwdMode {
+    /// In full, up to --cwd-max-dir-size
+    Full,
+    /// Only the first letter, like fish does
+    Fish,
+    /// The shortest prefix no other directory next to it starts with, so
+    /// the path can still be tab completed
+    Unique,
+    /// Relative to the root of the repository, or in full outside of one
+    Repo,
+    /// In full, but as a single segment joined by --cwd-separator
+    Plain
+}
+
+/// Options for the cwd module, from the command line
+pub struct CwdOptions {
+    pub max_depth: u8,
+    pub max_dir_size: u8,
+    /// Maximum width of all cwd segments together, 0 if unlimited
+    pub max_length: usize,
+    pub mode: CwdMode,
+    /// What joins the directories in plain mode
+    pub separator: String,
+    /// Named directories passed on by the shell, like zsh's `hash -d`
+    pub named_dirs: Vec<(String, PathBuf)>
+}
+
+/// How the directories are shown in this prompt
+pub struct PathStyle {
+    /// The colours, which a colour rule from the theme may change for this
+    /// prompt only
+    pub bg: u8,
+    pub fg: u8,
+    /// The foreground of the current directory
+    pub cwd_fg: u8,
+    /// The host to link the directories on, `None` without hyperlinks
+    pub host: Option<String>
+}
+
+/// Find the longest prefix of `path` that is shown as a single segment: a
+/// `cwd_alias` from the theme, a named directory or the home directory.
+/// Returns the prefix, the text to show and its colours.
+fn find_alias(p: &Powerline, path: &Path, named_dirs: &[(String, PathBuf)]) -> Option<(PathBuf, String, u8, u8)> {
+    let aliases = p.theme.cwd_aliases.iter()
+        .map(|alias| (PathBuf::from(expand_home(&alias.prefix)), alias.text.to_string(), alias.bg, alias.fg));
+    let named = named_dirs.iter()
+        .map(|(name, dir)| (dir.clone(), format!("~{}", name), p.theme.home_bg, p.theme.home_fg));
+    let home = dirs::home_dir()
+        .map(|home| (home, String::from("~"), p.theme.home_bg, p.theme.home_fg));
+
+    let mut found: Option<(PathBuf, String, u8, u8)> = None;
+    for candidate in aliases.chain(named).chain(home) {
+        if !path.starts_with(&candidate.0) {
+            continue;
         }
-        if let Some(new) = new_path {
-            path = new;
+        // The first of equally long ones wins, so the theme comes first
+        let length = candidate.0.components().count();
+        if found.as_ref().map(|found| length > found.0.components().count()).unwrap_or(true) {
+            found = Some(candidate);
         }
     }
+    found
+}
+
+/// Link the segment to `path` if hyperlinks are enabled
+fn link(style: &PathStyle, segment: Segment, path: &Path) -> Segment {
+    match style.host {
+        Some(ref host) => segment.with_link(file_url(host, path)),
+        None => segment
+    }
+}
+
+/// The current directory as the shell sees it, keeping the symlinks it was
+/// entered through, and whether it has been deleted since
+pub fn logical_cwd() -> (PathBuf, bool) {
+    let pwd = env::var_os("PWD").map(PathBuf::from).filter(|pwd| pwd.is_absolute());
+    let physical = match env::current_dir() {
+        Ok(physical) => physical,
+        Err(_) => return (pwd.unwrap_or_else(|| PathBuf::from("?")), true)
+    };
+    // $PWD may be stale, e.g. when powerline-rs wasn't started by the shell
+    let same = pwd.as_ref()
+        .and_then(|pwd| fs::metadata(pwd).ok())
+        .and_then(|logical| fs::metadata(&physical).ok().map(|physical| (logical, physical)))
+        .map(|(logical, physical)| logical.dev() == physical.dev() && logical.ino() == physical.ino())
+        .unwrap_or(false);
+    (if same { pwd.unwrap() } else { physical }, false)
+}
+
+/// Push the name of the repository root and the path inside it. Returns
+/// false when we're not in a repository.
+fn segment_cwd_repo(p: &mut Powerline, cwd_max_dir_size: u8, style: &PathStyle) -> bool {
+    let root = match super::work_tree(p) {
+        Some(root) => root,
+        None => return false
+    };
+    let cwd = match env::current_dir() {
+        Ok(cwd) => cwd,
+        Err(_) => return false
+    };
+    let path = match cwd.strip_prefix(&root) {
+        Ok(path) => path,
+        // In the git directory
+        Err(_) => return false
+    };
+    let name = match root.file_name() {
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => return false
+    };
+    let anchor = link(style, Segment::new(p.theme.cwd_repo_bg, p.theme.cwd_repo_fg, name), &root);
+    p.segments.push(anchor);
+
+    let mut parent = root.clone();
+    let mut dirs = path.iter();
+    let mut next = dirs.next();
+    while let Some(cursor) = next {
+        next = dirs.next();
+
+        segment(p, &parent, cursor, next.is_none(), cwd_max_dir_size, CwdMode::Full, style);
+        parent.push(cursor);
+    }
+    true
+}
+
+pub fn segment_cwd(p: &mut Powerline, options: &CwdOptions) {
+    let (cwd_max_depth, cwd_max_dir_size, mode) = (options.max_depth, options.max_dir_size, options.mode);
+    let (mut path, deleted) = logical_cwd();
+
+    let (bg, fg, cwd_fg) = {
+        let cwd = path.to_string_lossy();
+        p.theme.cwd_colors.iter()
+            .find(|rule| glob::matches(&expand_home(&rule.pattern), &cwd))
+            .map(|rule| (rule.path_bg, rule.path_fg, rule.cwd_fg))
+            .unwrap_or((p.theme.path_bg, p.theme.path_fg, p.theme.cwd_fg))
+    };
+    let style = PathStyle {
+        bg,
+        fg,
+        cwd_fg,
+        host: if p.hyperlinks { Some(hostname().unwrap_or_default()) } else { None }
+    };
+
+    let start = p.segments.len();
+    if mode == CwdMode::Repo && segment_cwd_repo(p, cwd_max_dir_size, &style) {
+        fit(p, start, 1, options.max_length, &style, None);
+        return;
+    }
+
+
+    let mut anchors = 0;
+    // The directory the component being shown is in
+    let mut parent = PathBuf::new();
+    if let Some((prefix, text, bg, fg)) = find_alias(p, &path, &options.named_dirs) {
+        let anchor = link(&style, Segment::new(bg, fg, text), &prefix);
+        p.segments.push(anchor);
+        anchors = 1;
+        path = path.strip_prefix(&prefix).unwrap().to_path_buf();
+        parent = prefix;
+    }
 
     let length = path.iter().count();
     let mut dirs = path.iter();
@@ -28,7 +183,8 @@ pub fn segment_cwd(p: &mut Powerline, cwd_max_depth: u8, cwd_max_dir_size: u8) {
     if cwd_max_depth != 1 {
         if let Some(dir) = dirs.next() {
             // Either there's no cwd_max_depth, or it's bigger than 1
-            segment(p, dir, length == 1, cwd_max_dir_size);
+            segment(p, &parent, dir, length == 1, cwd_max_dir_size, mode, &style);
+            parent.push(dir);
 
             // It would be sane here to subtract 1 from both length and
             // cwd_max_depth, to make it clear that we already tried one and
@@ -37,10 +193,10 @@ pub fn segment_cwd(p: &mut Powerline, cwd_max_depth: u8, cwd_max_dir_size: u8) {
         }
     }
     if cwd_max_depth > 0 && length > cwd_max_depth {
-        p.segments.push(Segment::new(p.theme.path_bg, p.theme.path_fg, Cow::from("…")));
+        p.segments.push(Segment::new(style.bg, style.fg, Cow::from("…")));
 
         for _ in 0..length - cwd_max_depth {
-            dirs.next().unwrap();
+            parent.push(dirs.next().unwrap());
         }
     }
 
@@ -48,14 +204,170 @@ pub fn segment_cwd(p: &mut Powerline, cwd_max_depth: u8, cwd_max_dir_size: u8) {
     while let Some(cursor) = next {
         next = dirs.next();
 
-        segment(p, cursor, next.is_none(), cwd_max_dir_size);
+        segment(p, &parent, cursor, next.is_none(), cwd_max_dir_size, mode, &style);
+        parent.push(cursor);
+    }
+
+    let plain = if mode == CwdMode::Plain { Some(&*options.separator) } else { None };
+    fit(p, start, anchors, options.max_length, &style, plain);
+
+    if mode == CwdMode::Plain && p.segments.len() > start {
+        let string = join(&p.segments[start..], &options.separator);
+        p.segments.truncate(start);
+        let plain = link(&style, Segment::new(style.bg, style.cwd_fg, string), &parent);
+        p.segments.push(plain);
+    }
+
+    if deleted {
+        let mut string = String::with_capacity(12);
+        string.push(p.theme.cwd_deleted_char);
+        string.push_str(" deleted");
+        p.segments.push(Segment::new(p.theme.cwd_deleted_bg, p.theme.cwd_deleted_fg, string));
     }
 }
-pub fn segment(p: &mut Powerline, name: &OsStr, last: bool, cwd_max_dir_size: u8) {
+/// The text of segments joined into one for plain mode
+fn join(segments: &[Segment], separator: &str) -> String {
+    let mut string = String::new();
+    for segment in segments {
+        // The root directory needs no separator after it
+        if !string.is_empty() && !string.ends_with('/') {
+            string.push_str(separator);
+        }
+        string.push_str(&segment.text);
+    }
+    string
+}
+/// The columns taken by segments, counting the padding and the separator. In
+/// plain mode they're measured as if joined by the `plain` separator.
+fn width(segments: &[Segment], plain: Option<&str>) -> usize {
+    match plain {
+        Some(separator) => join(segments, separator).chars().count() + 3,
+        None => segments.iter().map(|segment| segment.text.chars().count() + 3).sum()
+    }
+}
+/// Make the cwd segments from `start` on fit in `max_length` columns. The
+/// first `anchors` (home, an alias or the repository) are left alone. The
+/// directories in between are shortened to their first letter, then replaced
+/// by `…` from the left, and as a last resort the current one is truncated.
+fn fit(p: &mut Powerline, start: usize, anchors: usize, max_length: usize, style: &PathStyle, plain: Option<&str>) {
+    let first = start + anchors;
+    if max_length == 0 || p.segments.len() <= first {
+        return;
+    }
+    let last = p.segments.len() - 1;
+    let symlink = p.theme.cwd_symlink_char;
+
+    for i in first..last {
+        if width(&p.segments[start..], plain) <= max_length {
+            return;
+        }
+        let segment = &mut p.segments[i];
+        if segment.text == "…" {
+            continue;
+        }
+        let mut text = first_letter(&segment.text);
+        if segment.text.ends_with(symlink) {
+            text.push(' ');
+            text.push(symlink);
+        }
+        segment.text = Cow::Owned(text);
+    }
+
+    // Always keep the current directory
+    while width(&p.segments[start..], plain) > max_length && p.segments.len() - first > 1 {
+        if p.segments[first].text != "…" {
+            p.segments[first] = Segment::new(style.bg, style.fg, "…");
+        } else if p.segments.len() - first > 2 {
+            p.segments.remove(first + 1);
+        } else {
+            break;
+        }
+    }
+
+    let excess = width(&p.segments[start..], plain).saturating_sub(max_length);
+    if excess > 0 {
+        let segment = p.segments.last_mut().unwrap();
+        if let Some(text) = truncate(&segment.text, excess) {
+            segment.text = Cow::Owned(text);
+        }
+    }
+}
+/// Cut `excess` columns off `name`, ending it with a `…`. Returns `None` when
+/// that wouldn't make it any narrower, since at least one letter is kept.
+fn truncate(name: &str, excess: usize) -> Option<String> {
+    let count = name.chars().count();
+    // Leave room for the … and at least one letter
+    let keep = count.saturating_sub(excess + 1).max(1);
+    if keep + 1 >= count {
+        return None;
+    }
+    let mut text: String = name.chars().take(keep).collect();
+    text.push('…');
+    Some(text)
+}
+/// The first letter of a directory name, keeping any leading dots so hidden
+/// directories stay recognizable
+fn first_letter(name: &str) -> String {
+    let dots = name.len() - name.trim_start_matches('.').len();
+    let letter = name[dots..].chars().next().map(char::len_utf8).unwrap_or(0);
+    name[..dots + letter].to_string()
+}
+/// The shortest prefix of `name` that no other directory in `parent` starts
+/// with. Leading dots don't count towards the length, like in first_letter.
+fn unique_prefix(parent: &Path, name: &str) -> Option<String> {
+    let entries = fs::read_dir(parent).ok()?;
+    let mut siblings = Vec::new();
+    for (i, entry) in entries.enumerate() {
+        if i >= UNIQUE_MAX_ENTRIES {
+            return None;
+        }
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(_) => continue
+        };
+        if entry.file_type().map(|kind| kind.is_dir()).unwrap_or(false) {
+            let sibling = entry.file_name().to_string_lossy().into_owned();
+            if sibling != name {
+                siblings.push(sibling);
+            }
+        }
+    }
+
+    let dots = name.len() - name.trim_start_matches('.').len();
+    let mut end = dots;
+    for c in name[dots..].chars() {
+        end += c.len_utf8();
+        let prefix = &name[..end];
+        if !siblings.iter().any(|sibling| sibling.starts_with(prefix)) {
+            return Some(prefix.to_string());
+        }
+    }
+    Some(name.to_string())
+}
+pub fn segment(
+    p: &mut Powerline,
+    parent: &Path,
+    name: &OsStr,
+    last: bool,
+    cwd_max_dir_size: u8,
+    mode: CwdMode,
+    style: &PathStyle
+) {
+    let target = parent.join(name);
+    let symlink = fs::symlink_metadata(&target)
+        .map(|metadata| metadata.file_type().is_symlink())
+        .unwrap_or(false);
     let mut name = name.to_string_lossy().into_owned();
 
     let cwd_max_dir_size = cwd_max_dir_size as usize;
-    if cwd_max_dir_size > 0 && name.chars().count() > cwd_max_dir_size {
+    if mode == CwdMode::Fish && !last {
+        name = first_letter(&name);
+    } else if mode == CwdMode::Unique && !last {
+        // Directories too big to list are kept whole, which is still unique
+        if let Some(prefix) = unique_prefix(parent, &name) {
+            name = prefix;
+        }
+    } else if cwd_max_dir_size > 0 && name.chars().count() > cwd_max_dir_size {
         let mut start = 0;
         for c in name.chars().take(cwd_max_dir_size) {
             start += c.len_utf8();
@@ -64,6 +376,27 @@ pub fn segment(p: &mut Powerline, name: &OsStr, last: bool, cwd_max_dir_size: u8
         name.push('…');
     }
 
-    let fg = if last { p.theme.cwd_fg } else { p.theme.path_fg };
-    p.segments.push(Segment::new(p.theme.path_bg, fg, name));
+    if symlink {
+        name.push(' ');
+        name.push(p.theme.cwd_symlink_char);
+    }
+
+    let fg = if last { style.cwd_fg } else { style.fg };
+    let dir = link(style, Segment::new(style.bg, fg, name), &target);
+    p.segments.push(dir);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncating() {
+        assert_eq!(truncate("powerline-rs", 4).as_deref(), Some("powerli…"));
+        assert_eq!(truncate("powerline-rs", 100).as_deref(), Some("p…"));
+        // Nothing to gain for short names
+        assert_eq!(truncate("er", 5), None);
+        assert_eq!(truncate("abc", 5).as_deref(), Some("a…"));
+        assert_eq!(truncate("ab", 1), None);
+    }
 }
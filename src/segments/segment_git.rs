@@ -1,193 +1,289 @@
-#[cfg(feature = "flame")] use flame;
-use crate::{Powerline, Segment};
-use git2::{BranchType, ObjectType, Repository, Status, StatusOptions, StatusShow};
+use crate::{glob, Powerline, Segment};
+use super::{jj_root, JjGit};
+use std::{
+    env,
+    path::PathBuf
+};
 
-fn discover_if_none(git: &mut Option<Repository>) -> bool {
-    #[cfg(feature = "flame")]
-    let _guard = flame::start_guard("git discover");
-
-    if git.is_none() {
-        *git = Repository::discover(".").ok();
-        git.is_some()
-    } else { true }
-}
-fn statuses_if_none(git: &Repository, statuses: &mut Option<Vec<Status>>) -> bool {
-    #[cfg(feature = "flame")]
-    let _guard = flame::start_guard("git status");
-
-    if statuses.is_none() {
-        *statuses = git.statuses(Some(
-                StatusOptions::new()
-                    .show(StatusShow::IndexAndWorkdir)
-                    .include_untracked(true)
-                    .renames_from_rewrites(true)
-                    .renames_head_to_index(true)
-            ))
-            .ok()
-            .map(|statuses|
-                statuses.iter()
-                .map(|entry| entry.status())
-                .collect());
-        statuses.is_some()
-    } else { true }
+/// Per-repository settings, read from the `powerline-rs` section of the
+/// repository's git config (e.g. `git config powerline-rs.status false`).
+pub struct RepoConfig {
+    /// Hide all git segments for this repository
+    pub hide: bool,
+    /// Collect file statuses (dirty state and gitstage counts)
+    pub status: bool,
+    /// Include untracked files when collecting statuses
+    pub untracked: bool
 }
-
-pub fn segment_git(p: &mut Powerline) {
-    #[cfg(feature = "flame")]
-    let _guard = flame::start_guard("segment git");
-
-    if !discover_if_none(&mut p.git) {
-        return;
+impl Default for RepoConfig {
+    fn default() -> Self {
+        RepoConfig {
+            hide: false,
+            status: true,
+            untracked: true
+        }
     }
-    let git = p.git.as_ref().unwrap();
-
-    #[cfg(feature = "flame")]
-    flame::start("iter branches");
-
-    let branches = git.branches(Some(BranchType::Local));
-    if branches.is_err() {
-        return;
+}
+impl RepoConfig {
+    /// Apply a single `powerline-rs.*` key, ignoring unknown ones
+    pub fn set(&mut self, key: &str, value: bool) {
+        match key {
+            "powerline-rs.hide" => self.hide = value,
+            "powerline-rs.status" => self.status = value,
+            "powerline-rs.untracked" => self.untracked = value,
+            _ => ()
+        }
     }
+}
 
-    let mut branch_name = None;
-    let mut local    = None;
-    let mut upstream = None;
-
-    for branch in branches.unwrap() {
-        if let Ok((branch, _)) = branch {
-            if branch.is_head() {
-                local    = branch.get().target();
-                upstream = branch.upstream().ok().and_then(|b| b.get().target());
+/// A work tree whose repository lives somewhere else, set with --git-work-trees
+pub struct WorkTreeMapping {
+    pub work_tree: PathBuf,
+    pub git_dir: PathBuf
+}
 
-                if let Ok(name) = branch.name() {
-                    if let Some(name) = name {
-                        branch_name = Some(name.to_string());
-                        break;
-                    }
-                }
-            }
+/// Replace a leading `~` with the home directory
+pub fn expand_home(path: &str) -> String {
+    if path == "~" || path.starts_with("~/") {
+        if let Some(home) = dirs::home_dir() {
+            let mut expanded = home.to_string_lossy().into_owned();
+            expanded.push_str(&path[1..]);
+            return expanded;
         }
     }
+    path.to_string()
+}
+/// Whether git segments are hidden in the current directory by --git-hide.
+/// That's only for repositories from --git-work-trees, since any other one
+/// was found because we're inside of it.
+pub fn hidden_here(patterns: &[String], work_trees: &[WorkTreeMapping]) -> bool {
+    if patterns.is_empty() || mapped_repository(work_trees).is_none() {
+        return false;
+    }
+    let cwd = match env::current_dir() {
+        Ok(cwd) => cwd,
+        Err(_) => return false
+    };
+    let cwd = cwd.to_string_lossy();
+    patterns.iter().any(|pattern| glob::matches_path(pattern.trim_end_matches('/'), &cwd))
+}
+/// The mapping for the current directory, unless git would find another
+/// repository itself: either through $GIT_DIR or because one nested in the
+/// mapped work tree is closer.
+pub fn mapped_repository(work_trees: &[WorkTreeMapping]) -> Option<&WorkTreeMapping> {
+    if work_trees.is_empty() || env::var_os("GIT_DIR").is_some() {
+        return None;
+    }
+    let cwd = env::current_dir().ok()?;
+    let mapping = work_trees.iter()
+        .filter(|mapping| cwd.starts_with(&mapping.work_tree))
+        .max_by_key(|mapping| mapping.work_tree.components().count())?;
 
-    #[cfg(feature = "flame")]
-    flame::end("iter branches");
+    let nested = cwd.ancestors()
+        .take_while(|dir| dir.starts_with(&mapping.work_tree))
+        .any(|dir| dir.join(".git").exists());
+    if nested { None } else { Some(mapping) }
+}
 
-    if branch_name.is_none() {
-        #[cfg(feature = "flame")]
-        let _guard = flame::start_guard("search head");
+/// Extra details about where in a repository we are, shown by the git segment
+#[cfg(feature = "git2")]
+#[derive(Default)]
+pub struct GitIndicators {
+    pub remote: bool,
+    pub worktree: bool,
+    pub submodule: bool,
+    pub shallow: bool,
+    pub sparse: bool
+}
 
-        // Could be a detached head
-        if let Ok(head) = git.head() {
-            if let Some(target) = head.target() {
-                branch_name = git.find_object(target, Some(ObjectType::Any))
-                            .ok()
-                            .and_then(|obj| obj.short_id().ok())
-                            .and_then(|buf| buf.as_str()
-                                                .map(|s| s.to_string()))
-            }
-        } else {
-            p.segments.push(Segment::new(p.theme.git_dirty_bg, p.theme.git_dirty_fg, "Big Bang"));
-            return;
+/// Which changes make a submodule count as dirty, like git's
+/// `--ignore-submodules`
+#[derive(Clone, Copy, Eq, PartialEq)]
+pub enum IgnoreSubmodules {
+    /// Any change or untracked file
+    None,
+    /// Only changes to tracked files
+    Untracked,
+    /// Only a moved HEAD
+    Dirty,
+    /// Never
+    All
+}
+impl IgnoreSubmodules {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            IgnoreSubmodules::None      => "none",
+            IgnoreSubmodules::Untracked => "untracked",
+            IgnoreSubmodules::Dirty     => "dirty",
+            IgnoreSubmodules::All       => "all"
         }
     }
+}
 
-    if !statuses_if_none(git, &mut p.git_statuses) {
-        return;
+/// The working tree state, counted the same way by every backend
+#[derive(Clone, Copy, Default)]
+pub struct GitStatus {
+    pub changes: usize,
+    pub staged: usize,
+    pub notstaged: usize,
+    pub untracked: usize,
+    pub conflicted: usize,
+    pub submodules: usize
+}
+impl GitStatus {
+    pub fn is_clean(&self) -> bool {
+        self.changes == 0
     }
-    let statuses = p.git_statuses.as_ref().unwrap();
+}
 
-    let (mut bg, mut fg) = (p.theme.git_dirty_bg, p.theme.git_dirty_fg);
-    if statuses.is_empty() {
-        bg = p.theme.git_clean_bg;
-        fg = p.theme.git_clean_fg;
+pub fn segment_git(p: &mut Powerline) {
+    let jj = p.git_jj != JjGit::Show && jj_root().is_some();
+    if jj && p.git_jj == JjGit::Hide {
+        return;
     }
-    p.segments.push(Segment::new(bg, fg, branch_name.unwrap()));
+    p.git_branch = None;
 
-    #[cfg(feature = "flame")]
-    let _guard = flame::start_guard("checking remotes");
-
-    if let Some(local) = local {
-        if let Some(upstream) = upstream {
-            if let Ok((ahead, behind)) = git.graph_ahead_behind(local, upstream) {
-                if ahead > 0 {
-                    let mut ahead = if ahead == 1 { String::new() } else { ahead.to_string() };
-                    ahead.push(p.theme.git_ahead_char);
-                    p.segments.push(Segment::new(p.theme.git_ahead_bg, p.theme.git_ahead_fg, ahead));
-                }
+    #[cfg(feature = "git2")]
+    {
+        if !p.git_cli {
+            super::git_libgit2::segment_git(p);
+        } else {
+            super::git_cli::segment_git(p);
+        }
+    }
+    #[cfg(not(feature = "git2"))]
+    super::git_cli::segment_git(p);
 
-                if behind > 0 {
-                    let mut behind = if behind == 1 { String::new() } else { behind.to_string() };
-                    behind.push(p.theme.git_behind_char);
-                    p.segments.push(Segment::new(p.theme.git_behind_bg, p.theme.git_behind_fg, behind));
-                }
-            }
+    if jj {
+        // Mark the branch (really a detached HEAD) as managed by jj
+        if let Some(index) = p.git_branch {
+            let mut prefix = p.theme.jj_char.to_string();
+            prefix.push(' ');
+            p.segments[index].prepend(&prefix);
         }
     }
 }
 pub fn segment_gitstage(p: &mut Powerline) {
-    #[cfg(feature = "flame")]
-    let _guard = flame::start_guard("segment gitstage");
-
-    if !discover_if_none(&mut p.git) {
+    if p.git_jj == JjGit::Hide && jj_root().is_some() {
         return;
     }
-    let git = p.git.as_ref().unwrap();
-
-    if !statuses_if_none(git, &mut p.git_statuses) {
-        return;
+    #[cfg(feature = "git2")]
+    {
+        if !p.git_cli {
+            return super::git_libgit2::segment_gitstage(p);
+        }
     }
-    let statuses = p.git_statuses.as_ref().unwrap();
-
-    #[cfg(feature = "flame")]
-    flame::start("counting");
-
-    let mut staged = 0;
-    let mut notstaged = 0;
-    let mut untracked = 0;
-    let mut conflicted = 0;
+    super::git_cli::segment_gitstage(p)
+}
 
-    for status in statuses {
-        if status.contains(Status::INDEX_NEW)
-            || status.contains(Status::INDEX_MODIFIED)
-            || status.contains(Status::INDEX_TYPECHANGE)
-            || status.contains(Status::INDEX_RENAMED)
-            || status.contains(Status::INDEX_DELETED) {
-            staged += 1;
-        }
-        if status.contains(Status::WT_MODIFIED)
-            || status.contains(Status::WT_TYPECHANGE)
-            || status.contains(Status::WT_DELETED) {
-            notstaged += 1;
-        }
-        if status.contains(Status::WT_NEW) {
-            untracked += 1;
+/// The name of the current branch, or the short commit id if detached
+pub fn branch_name(p: &mut Powerline) -> Option<String> {
+    if p.git_jj == JjGit::Hide && jj_root().is_some() {
+        return None;
+    }
+    #[cfg(feature = "git2")]
+    {
+        if !p.git_cli {
+            return super::git_libgit2::branch_name(p);
         }
-        if status.contains(Status::CONFLICTED) {
-            conflicted += 1;
+    }
+    super::git_cli::branch_name(p)
+}
+
+/// The root of the working tree we're in
+pub fn work_tree(p: &mut Powerline) -> Option<PathBuf> {
+    #[cfg(feature = "git2")]
+    {
+        if !p.git_cli {
+            return super::git_libgit2::work_tree(p);
         }
     }
+    super::git_cli::work_tree(p)
+}
 
-    #[cfg(feature = "flame")]
-    flame::end("counting");
+/// Push the segment for a repository without any commits
+pub fn push_big_bang(p: &mut Powerline) {
+    p.git_branch = Some(p.segments.len());
+    p.segments.push(Segment::new(p.theme.git_dirty_bg, p.theme.git_dirty_fg, "Big Bang"));
+}
+/// Push the branch segment, linked to `url` if any. `status` is `None` when
+/// status collection is disabled for the repository.
+pub fn push_branch(p: &mut Powerline, branch_name: String, status: Option<&GitStatus>, url: Option<String>) {
+    let (bg, fg) = match status {
+        Some(status) if !status.is_clean() => (p.theme.git_dirty_bg, p.theme.git_dirty_fg),
+        // Without any statuses we can't know whether it's dirty. Don't paint
+        // it red for no reason.
+        _ => (p.theme.git_clean_bg, p.theme.git_clean_fg)
+    };
+    let mut segment = Segment::new(bg, fg, branch_name);
+    if let Some(url) = url {
+        segment = segment.with_link(url);
+    }
+    p.git_branch = Some(p.segments.len());
+    p.segments.push(segment);
+}
+/// Push the segment naming the linked worktree we're in
+#[cfg(feature = "git2")]
+pub fn push_worktree(p: &mut Powerline, name: &str) {
+    let mut string = String::with_capacity(name.len() + 4);
+    string.push(p.theme.git_worktree_char);
+    string.push(' ');
+    string.push_str(name);
+    p.segments.push(Segment::new(p.theme.git_worktree_bg, p.theme.git_worktree_fg, string));
+}
+/// Push the segment naming the superproject of the submodule we're in
+#[cfg(feature = "git2")]
+pub fn push_submodule(p: &mut Powerline, superproject: &str) {
+    let mut string = String::with_capacity(superproject.len() + 4);
+    string.push(p.theme.git_submodule_char);
+    string.push(' ');
+    string.push_str(superproject);
+    p.segments.push(Segment::new(p.theme.git_submodule_bg, p.theme.git_submodule_fg, string));
+}
+/// Push the branch segment for when collecting statuses ran out of time
+pub fn push_branch_unknown(p: &mut Powerline, mut branch_name: String) {
+    branch_name.push(' ');
+    branch_name.push(p.theme.git_unknown_char);
+    p.git_branch = Some(p.segments.len());
+    p.segments.push(Segment::new(p.theme.git_unknown_bg, p.theme.git_unknown_fg, branch_name));
+}
+pub fn push_ahead_behind(p: &mut Powerline, ahead: usize, behind: usize) {
+    if ahead > 0 {
+        let mut ahead = if ahead == 1 { String::new() } else { ahead.to_string() };
+        ahead.push(p.theme.git_ahead_char);
+        p.segments.push(Segment::new(p.theme.git_ahead_bg, p.theme.git_ahead_fg, ahead));
+    }
 
-    if staged > 0 {
-        let mut string = if staged == 1 { String::with_capacity(1) } else { staged.to_string() };
+    if behind > 0 {
+        let mut behind = if behind == 1 { String::new() } else { behind.to_string() };
+        behind.push(p.theme.git_behind_char);
+        p.segments.push(Segment::new(p.theme.git_behind_bg, p.theme.git_behind_fg, behind));
+    }
+}
+pub fn push_stage(p: &mut Powerline, status: &GitStatus) {
+    if status.staged > 0 {
+        let mut string = if status.staged == 1 { String::with_capacity(1) } else { status.staged.to_string() };
         string.push(p.theme.git_staged_char);
         p.segments.push(Segment::new(p.theme.git_staged_bg, p.theme.git_staged_fg, string));
     }
-    if notstaged > 0 {
-        let mut string = if notstaged == 1 { String::with_capacity(1) } else { notstaged.to_string() };
+    if status.notstaged > 0 {
+        let mut string = if status.notstaged == 1 { String::with_capacity(1) } else { status.notstaged.to_string() };
         string.push(p.theme.git_notstaged_char);
         p.segments.push(Segment::new(p.theme.git_notstaged_bg, p.theme.git_notstaged_fg, string));
     }
-    if untracked > 0 {
-        let mut string = if untracked == 1 { String::with_capacity(1) } else { untracked.to_string() };
+    if status.untracked > 0 {
+        let mut string = if status.untracked == 1 { String::with_capacity(1) } else { status.untracked.to_string() };
         string.push(p.theme.git_untracked_char);
         p.segments.push(Segment::new(p.theme.git_untracked_bg, p.theme.git_untracked_fg, string));
     }
-    if conflicted > 0 {
-        let mut string = if conflicted == 1 { String::with_capacity(1) } else { conflicted.to_string() };
+    if status.conflicted > 0 {
+        let mut string = if status.conflicted == 1 { String::with_capacity(1) } else { status.conflicted.to_string() };
         string.push(p.theme.git_conflicted_char);
         p.segments.push(Segment::new(p.theme.git_conflicted_bg, p.theme.git_conflicted_fg, string));
     }
+    if status.submodules > 0 {
+        let mut string = if status.submodules == 1 { String::with_capacity(1) } else { status.submodules.to_string() };
+        string.push(p.theme.git_dirty_submodules_char);
+        p.segments.push(Segment::new(p.theme.git_dirty_submodules_bg, p.theme.git_dirty_submodules_fg, string));
+    }
 }
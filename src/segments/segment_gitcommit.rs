@@ -0,0 +1,77 @@
+#[cfg(feature = "flame")] use flame;
+use crate::{format::fill, Powerline, Segment};
+use git2::Commit;
+use super::git_libgit2::discover_if_none;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Format how long ago `time` (in seconds since the epoch) was, like `3h`
+fn relative_age(time: i64) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_secs() as i64)
+        .unwrap_or(time);
+    let age = (now - time).max(0);
+
+    let (amount, unit) = match age {
+        age if age < 60          => (age, "s"),
+        age if age < 60*60       => (age / 60, "m"),
+        age if age < 60*60*24    => (age / (60*60), "h"),
+        age if age < 60*60*24*7  => (age / (60*60*24), "d"),
+        age if age < 60*60*24*30 => (age / (60*60*24*7), "w"),
+        age if age < 60*60*24*365 => (age / (60*60*24*30), "mo"),
+        age                      => (age / (60*60*24*365), "y")
+    };
+    let mut string = amount.to_string();
+    string.push_str(unit);
+    string
+}
+
+fn initials(name: &str) -> String {
+    name.split_whitespace()
+        .filter_map(|word| word.chars().next())
+        .flat_map(char::to_uppercase)
+        .collect()
+}
+
+fn subject(commit: &Commit, max_length: usize) -> String {
+    let mut subject = commit.summary().unwrap_or("").to_string();
+
+    if max_length > 0 && subject.chars().count() > max_length {
+        let mut start = 0;
+        for c in subject.chars().take(max_length) {
+            start += c.len_utf8();
+        }
+        subject.drain(start..);
+        subject.push('…');
+    }
+    subject
+}
+
+pub fn segment_gitcommit(p: &mut Powerline, format: &str, subject_max_length: usize) {
+    #[cfg(feature = "flame")]
+    let _guard = flame::start_guard("segment gitcommit");
+
+    if !discover_if_none(p) {
+        return;
+    }
+    let git = p.git.as_ref().unwrap();
+
+    let commit = match git.head().and_then(|head| head.peel_to_commit()) {
+        Ok(commit) => commit,
+        // No commits yet
+        Err(_) => return
+    };
+
+    let string = fill(format, |name| match name {
+        "hash" => Some(commit.as_object().short_id().ok()
+            .and_then(|id| id.as_str().map(String::from))
+            .unwrap_or_default()),
+        "age" => Some(relative_age(commit.time().seconds())),
+        "initials" => Some(initials(commit.author().name().unwrap_or(""))),
+        "subject" => Some(subject(&commit, subject_max_length)),
+        _ => None
+    });
+    drop(commit);
+
+    p.segments.push(Segment::new(p.theme.git_commit_bg, p.theme.git_commit_fg, string));
+}
@@ -0,0 +1,117 @@
+#[cfg(feature = "flame")] use flame;
+use crate::{Powerline, Segment};
+use git2::{Diff, DiffOptions, Patch};
+use super::{command::TimedOut, git_libgit2::{discover_if_none, with_deadline}};
+use std::time::Instant;
+
+/// The lines added and removed by a diff
+enum Lines {
+    Counted(usize, usize),
+    /// Too many files changed, or there was no time left
+    Unknown
+}
+
+/// Count the lines added and removed by `diff`, giving up after `max_files`
+/// files (0 means unlimited) or once the deadline passes
+fn line_stats(diff: Result<Diff, git2::Error>, max_files: usize, deadline: Option<Instant>) -> Option<Lines> {
+    let diff = diff.ok()?;
+    let (mut added, mut removed) = (0, 0);
+    // Generating the patches is what's slow, so check in between each
+    for i in 0..diff.deltas().len() {
+        if (max_files > 0 && i >= max_files) || deadline.map(|deadline| Instant::now() >= deadline).unwrap_or(false) {
+            return Some(Lines::Unknown);
+        }
+        if let Ok(Some(patch)) = Patch::from_diff(&diff, i) {
+            if let Ok((_, insertions, deletions)) = patch.line_stats() {
+                added += insertions;
+                removed += deletions;
+            }
+        }
+    }
+    Some(Lines::Counted(added, removed))
+}
+
+fn push_stats(p: &mut Powerline, prefix: Option<char>, lines: Lines) {
+    let mut string = String::with_capacity(16);
+    if let Some(prefix) = prefix {
+        string.push(prefix);
+    }
+    match lines {
+        Lines::Counted(0, 0) => return,
+        Lines::Counted(added, removed) => {
+            string.push('+');
+            string.push_str(&added.to_string());
+            string.push_str(" -");
+            string.push_str(&removed.to_string());
+        },
+        Lines::Unknown => {
+            string.push('+');
+            string.push(p.theme.git_unknown_char);
+        }
+    }
+    p.segments.push(Segment::new(p.theme.git_diff_bg, p.theme.git_diff_fg, string));
+}
+
+fn diff_options() -> DiffOptions {
+    let mut options = DiffOptions::new();
+    options.ignore_submodules(true);
+    options
+}
+
+pub fn segment_gitdiff(p: &mut Powerline, split: bool, max_files: usize) {
+    #[cfg(feature = "flame")]
+    let _guard = flame::start_guard("segment gitdiff");
+
+    if !discover_if_none(p) || p.git_timed_out {
+        return;
+    }
+    if !p.git_config.as_ref().unwrap().status {
+        return;
+    }
+
+    // If another segment already counted the changed files, there's no need
+    // to build a diff only to find out it's too big
+    let too_many = |files: usize| max_files > 0 && files > max_files;
+    let (staged_too_many, unstaged_too_many) = match p.git_status {
+        Some(status) if split => (too_many(status.staged), too_many(status.notstaged)),
+        Some(status) => (false, too_many(status.staged.max(status.notstaged))),
+        None => (false, false)
+    };
+
+    let deadline = p.git_deadline;
+    let stats = with_deadline(p.git.as_ref().unwrap(), deadline, move |git| {
+        // An unborn branch has no tree, which diffs like an empty one
+        let head = git.head().ok().and_then(|head| head.peel_to_tree().ok());
+        let staged = if !split {
+            None
+        } else if staged_too_many {
+            Some(Lines::Unknown)
+        } else {
+            line_stats(git.diff_tree_to_index(head.as_ref(), None, Some(&mut diff_options())), max_files, deadline)
+        };
+        let unstaged = if unstaged_too_many {
+            Some(Lines::Unknown)
+        } else if split {
+            line_stats(git.diff_index_to_workdir(None, Some(&mut diff_options())), max_files, deadline)
+        } else {
+            line_stats(git.diff_tree_to_workdir_with_index(head.as_ref(), Some(&mut diff_options())), max_files, deadline)
+        };
+        Some((staged, unstaged))
+    });
+    let (staged, unstaged) = match stats {
+        Ok(Some(stats)) => stats,
+        Ok(None) => return,
+        Err(TimedOut) => {
+            p.git_timed_out = true;
+            (None, Some(Lines::Unknown))
+        }
+    };
+
+    if let Some(staged) = staged {
+        push_stats(p, Some(p.theme.git_staged_char), staged);
+    }
+    if let Some(unstaged) = unstaged {
+        let prefix = if split { Some(p.theme.git_notstaged_char) } else { None };
+        push_stats(p, prefix, unstaged);
+    }
+}
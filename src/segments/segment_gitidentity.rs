@@ -0,0 +1,65 @@
+#[cfg(feature = "flame")] use flame;
+use crate::{glob, Powerline, Segment};
+use git2::Repository;
+use super::git_libgit2::{discover_if_none, remote_url};
+use std::env;
+
+/// The email git would use for new commits
+fn effective_email(git: &Repository) -> Option<String> {
+    if let Ok(email) = env::var("GIT_AUTHOR_EMAIL") {
+        return Some(email);
+    }
+    let config = git.config().ok();
+    config.as_ref().and_then(|config| config.get_string("author.email").ok())
+        .or_else(|| config.as_ref().and_then(|config| config.get_string("user.email").ok()))
+        .or_else(|| env::var("EMAIL").ok())
+}
+
+/// Check the author email against `rules`, which are `url-glob=domain` pairs.
+/// The first rule matching the remote URL decides the domain the email
+/// should have.
+pub fn segment_gitidentity<'a, I>(p: &mut Powerline, rules: I)
+    where I: IntoIterator<Item = &'a str>
+{
+    #[cfg(feature = "flame")]
+    let _guard = flame::start_guard("segment gitidentity");
+
+    if !discover_if_none(p) {
+        return;
+    }
+    let git = p.git.as_ref().unwrap();
+
+    let branch_name = git.head().ok()
+        .filter(|head| head.is_branch())
+        .and_then(|head| head.shorthand().map(String::from));
+    let url = match remote_url(git, branch_name.as_deref()) {
+        Some(url) => url,
+        None => return
+    };
+    let domain = rules.into_iter()
+        .filter_map(|rule| {
+            let mut parts = rule.splitn(2, '=');
+            Some((parts.next()?, parts.next()?))
+        })
+        .find(|(pattern, _)| glob::matches(pattern, &url))
+        .map(|(_, domain)| domain.trim_start_matches('@').to_lowercase());
+    let domain = match domain {
+        Some(domain) => domain,
+        None => return
+    };
+
+    let email = effective_email(git);
+    let matching = email.as_ref()
+        .and_then(|email| email.rsplit('@').next())
+        .map(|email_domain| email_domain.to_lowercase() == domain)
+        .unwrap_or(false);
+    if matching {
+        return;
+    }
+
+    let mut string = String::new();
+    string.push(p.theme.git_identity_char);
+    string.push(' ');
+    string.push_str(email.as_deref().unwrap_or("no email"));
+    p.segments.push(Segment::new(p.theme.git_identity_bg, p.theme.git_identity_fg, string));
+}
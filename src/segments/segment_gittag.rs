@@ -0,0 +1,45 @@
+#[cfg(feature = "flame")] use flame;
+use crate::{Powerline, Segment};
+use git2::{DescribeFormatOptions, DescribeOptions};
+use super::git_libgit2::discover_if_none;
+
+/// Split a long description, like `v2.3.1-4-gabc1234`, into the tag and the
+/// number of commits since it
+fn parse_describe(description: &str) -> Option<(&str, usize)> {
+    // Tags may contain dashes too, so split from the right
+    let mut parts = description.rsplitn(3, '-');
+    let _hash = parts.next()?;
+    let count = parts.next()?.parse().ok()?;
+    let tag = parts.next()?;
+    Some((tag, count))
+}
+
+pub fn segment_gittag(p: &mut Powerline) {
+    #[cfg(feature = "flame")]
+    let _guard = flame::start_guard("segment gittag");
+
+    if !discover_if_none(p) {
+        return;
+    }
+    let git = p.git.as_ref().unwrap();
+
+    // Fails when no tag can be reached from HEAD, or there are no commits
+    let description = match git.describe(DescribeOptions::new().describe_tags())
+            .and_then(|describe| describe.format(Some(
+                DescribeFormatOptions::new().always_use_long_format(true)
+            ))) {
+        Ok(description) => description,
+        Err(_) => return
+    };
+    let (tag, count) = match parse_describe(&description) {
+        Some(parsed) => parsed,
+        None => return
+    };
+
+    if count == 0 {
+        p.segments.push(Segment::new(p.theme.git_tag_bg, p.theme.git_tag_fg, tag.to_string()));
+    } else {
+        let string = format!("{}+{}", tag, count);
+        p.segments.push(Segment::new(p.theme.git_tag_past_bg, p.theme.git_tag_past_fg, string));
+    }
+}
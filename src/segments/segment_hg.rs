@@ -0,0 +1,157 @@
+#[cfg(feature = "flame")] use flame;
+use crate::{Powerline, Segment};
+use super::command::{self, TimedOut};
+use std::{
+    env,
+    fs,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    process::Command,
+    time::Instant
+};
+
+/// What reading the dirstate could tell about the working directory
+enum Dirstate {
+    Clean,
+    Dirty,
+    /// Needs comparing file contents (or a format we don't read), which is
+    /// left to hg itself
+    Unsure
+}
+
+fn find_root() -> Option<PathBuf> {
+    let cwd = env::current_dir().ok()?;
+    cwd.ancestors()
+        .find(|dir| dir.join(".hg").is_dir())
+        .map(Path::to_path_buf)
+}
+
+fn read_trimmed(path: &Path) -> Option<String> {
+    let content = fs::read_to_string(path).ok()?;
+    let content = content.trim();
+    if content.is_empty() { None } else { Some(content.to_string()) }
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from(bytes[0]) << 24 | u32::from(bytes[1]) << 16 | u32::from(bytes[2]) << 8 | u32::from(bytes[3])
+}
+
+/// Compare the entries of a version 1 dirstate with the files on disk, the
+/// same way `hg status` does before it falls back to reading contents.
+/// Untracked files aren't looked at, as that'd mean implementing .hgignore.
+fn read_dirstate(root: &Path, deadline: Option<Instant>) -> Result<Dirstate, TimedOut> {
+    #[cfg(feature = "flame")]
+    let _guard = flame::start_guard("read dirstate");
+
+    let requires = fs::read_to_string(root.join(".hg/requires")).unwrap_or_default();
+    if requires.lines().any(|line| line == "dirstate-v2") {
+        return Ok(Dirstate::Unsure);
+    }
+    let dirstate = match fs::read(root.join(".hg/dirstate")) {
+        Ok(dirstate) => dirstate,
+        // No files were ever added
+        Err(_) => return Ok(Dirstate::Clean)
+    };
+    // The header is the two 20 byte parents. A second one means a merge.
+    if dirstate.len() < 40 {
+        return Ok(Dirstate::Unsure);
+    }
+    if dirstate[20..40].iter().any(|&byte| byte != 0) {
+        return Ok(Dirstate::Dirty);
+    }
+
+    let mut unsure = false;
+    let mut rest = &dirstate[40..];
+    while !rest.is_empty() {
+        if deadline.map(|deadline| Instant::now() >= deadline).unwrap_or(false) {
+            return Err(TimedOut);
+        }
+        // state, mode, size, mtime and the length of the name, then the name
+        if rest.len() < 17 {
+            return Ok(Dirstate::Unsure);
+        }
+        let state = rest[0];
+        let mode = read_u32(&rest[1..5]);
+        let size = read_u32(&rest[5..9]) as i32;
+        let mtime = read_u32(&rest[9..13]) as i32;
+        let length = read_u32(&rest[13..17]) as usize;
+        if rest.len() < 17 + length {
+            return Ok(Dirstate::Unsure);
+        }
+        let name = &rest[17..17 + length];
+        rest = &rest[17 + length..];
+
+        if state != b'n' {
+            // Added, removed or merged
+            return Ok(Dirstate::Dirty);
+        }
+        // A copy source follows the name after a nul byte
+        let name = name.split(|&byte| byte == 0).next().unwrap_or(name);
+        let name = match std::str::from_utf8(name) {
+            Ok(name) => name,
+            Err(_) => {
+                unsure = true;
+                continue;
+            }
+        };
+        let metadata = match fs::symlink_metadata(root.join(name)) {
+            Ok(metadata) => metadata,
+            // Deleted
+            Err(_) => return Ok(Dirstate::Dirty)
+        };
+        // hg uses negative sizes for entries it has to look at again, and
+        // only keeps 31 bits of the rest
+        if size < 0 {
+            unsure = true;
+        } else if (metadata.size() & 0x7FFF_FFFF) as i32 != size || (metadata.mode() ^ mode) & 0o100 != 0 {
+            return Ok(Dirstate::Dirty);
+        } else if mtime < 0 || (metadata.mtime() & 0x7FFF_FFFF) as i32 != mtime {
+            unsure = true;
+        }
+    }
+    Ok(if unsure { Dirstate::Unsure } else { Dirstate::Clean })
+}
+
+/// Ask hg about changes to tracked files, `None` if it couldn't be run
+fn hg_dirty(root: &Path, deadline: Option<Instant>) -> Result<Option<bool>, TimedOut> {
+    #[cfg(feature = "flame")]
+    let _guard = flame::start_guard("hg status");
+
+    let output = command::output(Command::new("hg").args(["status", "--quiet"]).current_dir(root), deadline)?;
+    Ok(output.map(|output| !output.is_empty()))
+}
+
+pub fn segment_hg(p: &mut Powerline) {
+    #[cfg(feature = "flame")]
+    let _guard = flame::start_guard("segment hg");
+
+    let root = match find_root() {
+        Some(root) => root,
+        None => return
+    };
+
+    let mut string = read_trimmed(&root.join(".hg/branch")).unwrap_or_else(|| String::from("default"));
+    if let Some(bookmark) = read_trimmed(&root.join(".hg/bookmarks.current")) {
+        string.push(' ');
+        string.push(p.theme.hg_bookmark_char);
+        string.push_str(&bookmark);
+    }
+
+    let dirty = read_dirstate(&root, p.git_deadline).and_then(|dirstate| match dirstate {
+        Dirstate::Clean => Ok(false),
+        Dirstate::Dirty => Ok(true),
+        // Like push_branch, don't paint it red for no reason
+        Dirstate::Unsure => hg_dirty(&root, p.git_deadline).map(|dirty| dirty.unwrap_or(false))
+    });
+    let (bg, fg) = match dirty {
+        Ok(true) => (p.theme.hg_dirty_bg, p.theme.hg_dirty_fg),
+        Ok(false) => (p.theme.hg_clean_bg, p.theme.hg_clean_fg),
+        Err(TimedOut) => {
+            // Mark it like git does when it runs out of time
+            string.push(' ');
+            string.push(p.theme.git_unknown_char);
+            (p.theme.hg_clean_bg, p.theme.hg_clean_fg)
+        }
+    };
+    p.segments.push(Segment::new(bg, fg, string));
+}
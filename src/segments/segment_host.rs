@@ -9,20 +9,24 @@ extern "C" {
     fn gethostname(buf: *mut c_char, len: usize) -> c_int;
 }
 
+pub fn hostname() -> Option<String> {
+    let mut name = [0u8; 256];
+    if unsafe { gethostname(&mut name[0] as *mut _ as *mut c_char, name.len()) } == 0 {
+        let len = name.iter().position(|i| *i == 0).unwrap_or(name.len());
+
+        if let Ok(name) = str::from_utf8(&name[..len]) {
+            return Some(String::from(name));
+        }
+    }
+    None
+}
+
 pub fn segment_host(p: &mut Powerline) {
     let (bg, fg) = (p.theme.hostname_bg, p.theme.hostname_fg);
 
     if p.shell == Shell::Bare {
         // We don't want to dont_escape() here
-        let mut name = [0u8; 256];
-        let mut string = Cow::from("error");
-        if unsafe { gethostname(&mut name[0] as *mut _ as *mut c_char, name.len()) } == 0 {
-            let len = name.iter().position(|i| *i == 0).unwrap_or(name.len());
-
-            if let Ok(name) = str::from_utf8(&name[..len]) {
-                string = Cow::from(String::from(name));
-            }
-        }
+        let string = hostname().map(Cow::from).unwrap_or(Cow::from("error"));
         p.segments.push(Segment::new(bg, fg, string));
         return;
     }
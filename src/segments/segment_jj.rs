@@ -0,0 +1,68 @@
+#[cfg(feature = "flame")] use flame;
+use crate::{Powerline, Segment};
+use super::command;
+use std::{
+    env,
+    path::{Path, PathBuf},
+    process::Command
+};
+
+/// What the git segments do in a jj repository, where git only ever sees a
+/// detached HEAD
+#[derive(Clone, Copy, Eq, PartialEq)]
+pub enum JjGit {
+    Show,
+    Annotate,
+    Hide
+}
+
+/// The root of the jj repository we're in. A `.git` closer than any `.jj`
+/// means a plain git repository nested inside it.
+pub fn jj_root() -> Option<PathBuf> {
+    let cwd = env::current_dir().ok()?;
+    cwd.ancestors()
+        .find(|dir| dir.join(".jj").is_dir() || dir.join(".git").exists())
+        .filter(|dir| dir.join(".jj").is_dir())
+        .map(Path::to_path_buf)
+}
+
+// Fields are separated by the unit separator, which can't be in a bookmark
+const TEMPLATE: &str = r#"change_id.shortest(8) ++ "\x1f" ++ bookmarks.join(" ") ++ "\x1f" ++ if(conflict, "1", "0")"#;
+
+pub fn segment_jj(p: &mut Powerline) {
+    #[cfg(feature = "flame")]
+    let _guard = flame::start_guard("segment jj");
+
+    let root = match jj_root() {
+        Some(root) => root,
+        None => return
+    };
+
+    // Snapshotting the working copy could take a while and would write to
+    // the repository, so only read what's already recorded
+    let mut jj = Command::new("jj");
+    jj.args(["log", "--ignore-working-copy", "--no-graph", "--color=never", "-r", "@", "-T", TEMPLATE])
+        .current_dir(&root);
+    let output = match command::output(&mut jj, p.git_deadline) {
+        Ok(Some(output)) => output,
+        _ => return
+    };
+
+    let mut fields = output.split('\x1f');
+    let mut string = fields.next().unwrap_or("").trim().to_string();
+    if string.is_empty() {
+        return;
+    }
+    if let Some(bookmarks) = fields.next().filter(|bookmarks| !bookmarks.is_empty()) {
+        string.push(' ');
+        string.push_str(bookmarks);
+    }
+
+    if fields.next() == Some("1") {
+        string.push(' ');
+        string.push(p.theme.jj_conflict_char);
+        p.segments.push(Segment::new(p.theme.jj_conflict_bg, p.theme.jj_conflict_fg, string));
+    } else {
+        p.segments.push(Segment::new(p.theme.jj_bg, p.theme.jj_fg, string));
+    }
+}
@@ -20,7 +20,7 @@ pub fn segment_ps(p: &mut Powerline) {
         write!(path, "{}", pid).unwrap();
         path.push_str("/stat");
 
-        match get_process_tty(&Path::new(&path)) {
+        match get_process_tty(Path::new(&path)) {
             Some(tty) => tty,
             None => return
         }
@@ -40,7 +40,7 @@ pub fn segment_ps(p: &mut Powerline) {
                     .and_then(|name| name
                         .to_str()
                         .map(|s| {
-                            s.chars().all(|c| c >= '0' && c <= '9')
+                            s.chars().all(|c| c.is_ascii_digit())
                                 && s.parse() != Ok(pid)
                         }))
                     .unwrap_or(false) {
@@ -60,7 +60,7 @@ pub fn segment_ps(p: &mut Powerline) {
     }
 }
 pub fn get_process_tty(file: &Path) -> Option<usize> {
-    fs::read_to_string(&file).ok()?
+    fs::read_to_string(file).ok()?
         .split_whitespace().nth(PROC_STAT_PID)
         .and_then(|n| n.parse().ok())
 }
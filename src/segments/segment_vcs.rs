@@ -0,0 +1,124 @@
+#[cfg(feature = "flame")] use flame;
+use crate::{Powerline, Segment};
+use super::command::{self, TimedOut};
+use std::{
+    env,
+    path::{Path, PathBuf},
+    process::Command,
+    time::Instant
+};
+
+/// Working copies of version control systems without a module of their own
+enum Vcs {
+    Svn,
+    Fossil
+}
+
+/// Find the closest working copy. Both keep their state in an SQLite database
+/// at the root, which is what we look for.
+fn find_checkout() -> Option<(Vcs, PathBuf)> {
+    let cwd = env::current_dir().ok()?;
+    cwd.ancestors().find_map(|dir| {
+        if dir.join(".svn/wc.db").is_file() {
+            Some((Vcs::Svn, dir.to_path_buf()))
+        } else if dir.join(".fslckout").is_file() || dir.join("_FOSSIL_").is_file() {
+            Some((Vcs::Fossil, dir.to_path_buf()))
+        } else { None }
+    })
+}
+
+/// Run a command in the working copy and return its output, or `None` if it
+/// failed or isn't installed
+fn run(root: &Path, program: &str, args: &[&str], deadline: Option<Instant>) -> Result<Option<String>, TimedOut> {
+    #[cfg(feature = "flame")]
+    let _guard = flame::start_guard(format!("{} command", program));
+
+    command::output(Command::new(program).args(args).current_dir(root), deadline)
+}
+
+/// The branch in a URL following the standard layout, like `^/project/trunk`
+/// or `^/project/branches/name/some/subdirectory`. Anything else falls back
+/// to the last part of the URL.
+fn svn_branch(url: &str) -> Option<&str> {
+    let mut parts = url.split('/').filter(|part| !part.is_empty() && *part != "^");
+    let mut last = None;
+    while let Some(part) = parts.next() {
+        match part {
+            "trunk" => return Some(part),
+            "branches" | "tags" => return parts.next().or(Some(part)),
+            _ => last = Some(part)
+        }
+    }
+    last
+}
+
+/// The branch, or `None` when the tool isn't available
+fn name(vcs: &Vcs, root: &Path, deadline: Option<Instant>) -> Result<Option<String>, TimedOut> {
+    let name = match vcs {
+        Vcs::Svn => run(root, "svn", &["info", "--show-item", "relative-url"], deadline)?
+            .and_then(|url| svn_branch(url.trim()).map(String::from)),
+        Vcs::Fossil => run(root, "fossil", &["branch", "current"], deadline)?
+            .map(|name| name.trim().to_string())
+    };
+    Ok(name.filter(|name| !name.is_empty()))
+}
+/// Whether there are local changes
+fn dirty(vcs: &Vcs, root: &Path, deadline: Option<Instant>) -> Result<bool, TimedOut> {
+    let output = match vcs {
+        Vcs::Svn    => run(root, "svn", &["status", "--quiet"], deadline)?,
+        Vcs::Fossil => run(root, "fossil", &["changes"], deadline)?
+    };
+    Ok(output.map(|output| !output.trim().is_empty()).unwrap_or(false))
+}
+
+pub fn segment_vcs(p: &mut Powerline) {
+    #[cfg(feature = "flame")]
+    let _guard = flame::start_guard("segment vcs");
+
+    let (vcs, root) = match find_checkout() {
+        Some(checkout) => checkout,
+        None => return
+    };
+    let mut string = String::with_capacity(16);
+    string.push(match vcs {
+        Vcs::Svn    => p.theme.vcs_svn_char,
+        Vcs::Fossil => p.theme.vcs_fossil_char
+    });
+    let dirty = match name(&vcs, &root, p.git_deadline) {
+        Ok(Some(name)) => {
+            string.push(' ');
+            string.push_str(&name);
+            dirty(&vcs, &root, p.git_deadline)
+        },
+        Ok(None) => return,
+        Err(TimedOut) => Err(TimedOut)
+    };
+
+    let (bg, fg) = match dirty {
+        Ok(true) => (p.theme.vcs_dirty_bg, p.theme.vcs_dirty_fg),
+        Ok(false) => (p.theme.vcs_clean_bg, p.theme.vcs_clean_fg),
+        Err(TimedOut) => {
+            // Mark it like git does when it runs out of time
+            string.push(' ');
+            string.push(p.theme.git_unknown_char);
+            (p.theme.vcs_clean_bg, p.theme.vcs_clean_fg)
+        }
+    };
+    p.segments.push(Segment::new(bg, fg, string));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn svn_branches() {
+        assert_eq!(svn_branch("^/trunk"), Some("trunk"));
+        assert_eq!(svn_branch("^/project/trunk/src/lib"), Some("trunk"));
+        assert_eq!(svn_branch("^/project/branches/feature/src"), Some("feature"));
+        assert_eq!(svn_branch("^/tags/1.0"), Some("1.0"));
+        assert_eq!(svn_branch("^/branches"), Some("branches"));
+        assert_eq!(svn_branch("^/project/src"), Some("src"));
+        assert_eq!(svn_branch("^/"), None);
+    }
+}
@@ -7,8 +7,7 @@ pub fn segment_virtualenv(p: &mut Powerline) {
         .or_else(|_| env::var("CONDA_DEFAULT_ENV"))
         .map(|env_path| {
             path::Path::new(&env_path)
-                .file_name()
-                .and_then(|env_name| Some(env_name.to_string_lossy().into_owned()))
+                .file_name().map(|env_name| env_name.to_string_lossy().into_owned())
         }) {
         p.segments.push(Segment::new(
             p.theme.virtual_env_bg,
@@ -1,3 +1,27 @@
+use std::borrow::Cow;
+
+/// A directory shown as a single segment, like the home directory is shown
+/// as `~`
+#[derive(Clone)]
+pub struct CwdAlias {
+    pub prefix: Cow<'static, str>,
+    pub text: Cow<'static, str>,
+    pub bg: u8,
+    pub fg: u8
+}
+
+/// Colours for the path when the current directory matches a glob
+#[derive(Clone)]
+pub struct CwdColor {
+    pub pattern: Cow<'static, str>,
+    pub path_bg: u8,
+    pub path_fg: u8,
+    pub cwd_fg: u8
+}
+
+/// Pairs of a glob matched against a remote URL and the icon to show for it
+pub type RemoteIcons = Cow<'static, [(Cow<'static, str>, char)]>;
+
 #[derive(Clone)]
 pub struct Theme {
     pub separator_fg: u8,
@@ -7,6 +31,14 @@ pub struct Theme {
     pub path_bg: u8,
     pub path_fg: u8,
     pub cwd_fg:  u8,
+    pub cwd_repo_bg: u8,
+    pub cwd_repo_fg: u8,
+    pub cwd_deleted_bg: u8,
+    pub cwd_deleted_fg: u8,
+    pub cwd_deleted_char: char,
+    pub cwd_symlink_char: char,
+    pub cwd_aliases: Cow<'static, [CwdAlias]>,
+    pub cwd_colors: Cow<'static, [CwdColor]>,
 
     pub username_bg: u8,
     pub username_fg: u8,
@@ -47,6 +79,24 @@ pub struct Theme {
     pub git_staged_fg:    u8,
     pub git_untracked_bg: u8,
     pub git_untracked_fg: u8,
+    pub git_commit_bg: u8,
+    pub git_commit_fg: u8,
+    pub git_tag_bg: u8,
+    pub git_tag_fg: u8,
+    pub git_tag_past_bg: u8,
+    pub git_tag_past_fg: u8,
+    pub git_diff_bg: u8,
+    pub git_diff_fg: u8,
+    pub git_identity_bg: u8,
+    pub git_identity_fg: u8,
+    pub git_dirty_submodules_bg: u8,
+    pub git_dirty_submodules_fg: u8,
+    pub git_unknown_bg: u8,
+    pub git_unknown_fg: u8,
+    pub git_worktree_bg: u8,
+    pub git_worktree_fg: u8,
+    pub git_submodule_bg: u8,
+    pub git_submodule_fg: u8,
 
     pub git_ahead_char: char,
     pub git_behind_char: char,
@@ -54,6 +104,35 @@ pub struct Theme {
     pub git_notstaged_char: char,
     pub git_untracked_char: char,
     pub git_conflicted_char: char,
+    pub git_identity_char: char,
+    pub git_dirty_submodules_char: char,
+    pub git_unknown_char: char,
+    pub git_worktree_char: char,
+    pub git_submodule_char: char,
+    pub git_shallow_char: char,
+    pub git_sparse_char: char,
+
+    pub git_remote_icons: RemoteIcons,
+
+    pub hg_clean_bg: u8,
+    pub hg_clean_fg: u8,
+    pub hg_dirty_bg: u8,
+    pub hg_dirty_fg: u8,
+    pub hg_bookmark_char: char,
+
+    pub jj_bg: u8,
+    pub jj_fg: u8,
+    pub jj_conflict_bg: u8,
+    pub jj_conflict_fg: u8,
+    pub jj_char: char,
+    pub jj_conflict_char: char,
+
+    pub vcs_clean_bg: u8,
+    pub vcs_clean_fg: u8,
+    pub vcs_dirty_bg: u8,
+    pub vcs_dirty_fg: u8,
+    pub vcs_svn_char: char,
+    pub vcs_fossil_char: char,
 
     pub cmd_passed_bg: u8,
     pub cmd_passed_fg: u8,
@@ -78,6 +157,14 @@ pub const DEFAULT: Theme = Theme {
     path_bg: 237,
     path_fg: 250,
     cwd_fg: 254,
+    cwd_repo_bg: 24,
+    cwd_repo_fg: 15,
+    cwd_deleted_bg: 196,
+    cwd_deleted_fg: 15,
+    cwd_deleted_char: '✖',
+    cwd_symlink_char: '↪',
+    cwd_aliases: Cow::Borrowed(&[]),
+    cwd_colors: Cow::Borrowed(&[]),
 
     username_bg: 240,
     username_fg: 250,
@@ -120,6 +207,24 @@ pub const DEFAULT: Theme = Theme {
     git_staged_fg: 15,
     git_untracked_bg: 52,
     git_untracked_fg: 15,
+    git_commit_bg: 236,
+    git_commit_fg: 250,
+    git_tag_bg: 30,
+    git_tag_fg: 15,
+    git_tag_past_bg: 237,
+    git_tag_past_fg: 250,
+    git_diff_bg: 238,
+    git_diff_fg: 250,
+    git_identity_bg: 196,
+    git_identity_fg: 15,
+    git_dirty_submodules_bg: 96,
+    git_dirty_submodules_fg: 15,
+    git_unknown_bg: 240,
+    git_unknown_fg: 250,
+    git_worktree_bg: 24,
+    git_worktree_fg: 15,
+    git_submodule_bg: 60,
+    git_submodule_fg: 15,
 
     git_ahead_char: '⬆',
     git_behind_char: '⬇',
@@ -127,6 +232,40 @@ pub const DEFAULT: Theme = Theme {
     git_notstaged_char: '✎',
     git_untracked_char: '+',
     git_conflicted_char: '*',
+    git_identity_char: '⚠',
+    git_dirty_submodules_char: '⊛',
+    git_unknown_char: '?',
+    git_worktree_char: '⧉',
+    git_submodule_char: '⊂',
+    git_shallow_char: '↧',
+    git_sparse_char: '⋯',
+
+    git_remote_icons: Cow::Borrowed(&[
+        (Cow::Borrowed("*github.com*"), '\u{f09b}'),
+        (Cow::Borrowed("*gitlab*"), '\u{f296}'),
+        (Cow::Borrowed("*bitbucket.org*"), '\u{f171}'),
+        (Cow::Borrowed("*"), '\u{f1d3}'),
+    ]),
+
+    hg_clean_bg: 148,
+    hg_clean_fg: 0,
+    hg_dirty_bg: 161,
+    hg_dirty_fg: 15,
+    hg_bookmark_char: '★',
+
+    jj_bg: 97,
+    jj_fg: 15,
+    jj_conflict_bg: 161,
+    jj_conflict_fg: 15,
+    jj_char: '⊚',
+    jj_conflict_char: '×',
+
+    vcs_clean_bg: 148,
+    vcs_clean_fg: 0,
+    vcs_dirty_bg: 161,
+    vcs_dirty_fg: 15,
+    vcs_svn_char: 'S',
+    vcs_fossil_char: 'F',
 
     cmd_passed_bg: 236,
     cmd_passed_fg: 15,
@@ -160,6 +299,7 @@ pub fn load(file: &str) -> Result<Theme, Box<dyn StdError>> {
     let reader = BufReader::new(file);
 
     let mut theme = DEFAULT.clone();
+    let mut remote_icons = 0;
 
     for line in reader.lines() {
         let line = line?;
@@ -168,20 +308,53 @@ pub fn load(file: &str) -> Result<Theme, Box<dyn StdError>> {
         }
         let mut parts = line.splitn(2, '=');
 
-        let variable = parts.next().map(|inner| inner.trim()).ok_or_else(|| ErrCorrupt)?;
-        let value    = parts.next().map(|inner| inner.trim()).ok_or_else(|| ErrCorrupt)?;
-
-        if variable.ends_with("char") {
-            let index = theme_index_char(&mut theme, variable).ok_or_else(|| ErrCorrupt)?;
-
-            if value.chars().count() == 1 {
-                *index = value.parse()?;
-            } else {
-                let codepoint = u32::from_str_radix(value, 16)?;
-                *index = std::char::from_u32(codepoint).ok_or_else(|| ErrCorrupt)?;
-            }
+        let variable = parts.next().map(|inner| inner.trim()).ok_or(ErrCorrupt)?;
+        let value    = parts.next().map(|inner| inner.trim()).ok_or(ErrCorrupt)?;
+
+        if variable == "cwd_alias" {
+            // Like "~/work/company W 24 15": the directory, the text to show
+            // instead, and its background and foreground
+            let mut parts = value.rsplitn(4, char::is_whitespace);
+            let fg = parts.next().ok_or(ErrCorrupt)?.parse()?;
+            let bg = parts.next().ok_or(ErrCorrupt)?.parse()?;
+            let text = parts.next().ok_or(ErrCorrupt)?.to_string();
+            let prefix = parts.next().ok_or(ErrCorrupt)?.trim().to_string();
+
+            theme.cwd_aliases.to_mut().push(CwdAlias {
+                prefix: Cow::Owned(prefix),
+                text: Cow::Owned(text),
+                bg,
+                fg
+            });
+        } else if variable == "cwd_color" {
+            // Like "/srv/prod* 124 15 15": the glob, then path_bg, path_fg
+            // and cwd_fg to use instead
+            let mut parts = value.rsplitn(4, char::is_whitespace);
+            let cwd_fg = parts.next().ok_or(ErrCorrupt)?.parse()?;
+            let path_fg = parts.next().ok_or(ErrCorrupt)?.parse()?;
+            let path_bg = parts.next().ok_or(ErrCorrupt)?.parse()?;
+            let pattern = parts.next().ok_or(ErrCorrupt)?.trim().to_string();
+
+            theme.cwd_colors.to_mut().push(CwdColor {
+                pattern: Cow::Owned(pattern),
+                path_bg,
+                path_fg,
+                cwd_fg
+            });
+        } else if variable == "git_remote_icon" {
+            // Like "*gitea.example.com* f1d3". Those in the theme file are
+            // checked before the defaults, in order.
+            let mut parts = value.rsplitn(2, char::is_whitespace);
+            let icon = parse_char(parts.next().ok_or(ErrCorrupt)?)?;
+            let pattern = parts.next().ok_or(ErrCorrupt)?.trim();
+
+            theme.git_remote_icons.to_mut().insert(remote_icons, (Cow::Owned(pattern.to_string()), icon));
+            remote_icons += 1;
+        } else if variable.ends_with("char") {
+            let index = theme_index_char(&mut theme, variable).ok_or(ErrCorrupt)?;
+            *index = parse_char(value)?;
         } else {
-            let index = theme_index_u8(&mut theme, variable).ok_or_else(|| ErrCorrupt)?;
+            let index = theme_index_u8(&mut theme, variable).ok_or(ErrCorrupt)?;
             *index = value.parse()?;
         }
     }
@@ -189,6 +362,15 @@ pub fn load(file: &str) -> Result<Theme, Box<dyn StdError>> {
     Ok(theme)
 }
 
+fn parse_char(value: &str) -> Result<char, Box<dyn StdError>> {
+    if value.chars().count() == 1 {
+        Ok(value.parse()?)
+    } else {
+        let codepoint = u32::from_str_radix(value, 16)?;
+        Ok(std::char::from_u32(codepoint).ok_or(ErrCorrupt)?)
+    }
+}
+
 fn theme_index_u8<'a>(theme: &'a mut Theme, name: &str) -> Option<&'a mut u8> {
     match name {
         "separator_fg" => Some(&mut theme.separator_fg),
@@ -198,6 +380,10 @@ fn theme_index_u8<'a>(theme: &'a mut Theme, name: &str) -> Option<&'a mut u8> {
         "path_bg" => Some(&mut theme.path_bg),
         "path_fg" => Some(&mut theme.path_fg),
         "cwd_fg"  => Some(&mut theme.cwd_fg),
+        "cwd_repo_bg" => Some(&mut theme.cwd_repo_bg),
+        "cwd_repo_fg" => Some(&mut theme.cwd_repo_fg),
+        "cwd_deleted_bg" => Some(&mut theme.cwd_deleted_bg),
+        "cwd_deleted_fg" => Some(&mut theme.cwd_deleted_fg),
 
         "username_bg" => Some(&mut theme.username_bg),
         "username_fg" => Some(&mut theme.username_fg),
@@ -234,6 +420,39 @@ fn theme_index_u8<'a>(theme: &'a mut Theme, name: &str) -> Option<&'a mut u8> {
         "git_staged_fg" => Some(&mut theme.git_staged_fg),
         "git_untracked_bg" => Some(&mut theme.git_untracked_bg),
         "git_untracked_fg" => Some(&mut theme.git_untracked_fg),
+        "git_commit_bg" => Some(&mut theme.git_commit_bg),
+        "git_commit_fg" => Some(&mut theme.git_commit_fg),
+        "git_tag_bg" => Some(&mut theme.git_tag_bg),
+        "git_tag_fg" => Some(&mut theme.git_tag_fg),
+        "git_tag_past_bg" => Some(&mut theme.git_tag_past_bg),
+        "git_tag_past_fg" => Some(&mut theme.git_tag_past_fg),
+        "git_diff_bg" => Some(&mut theme.git_diff_bg),
+        "git_diff_fg" => Some(&mut theme.git_diff_fg),
+        "git_identity_bg" => Some(&mut theme.git_identity_bg),
+        "git_identity_fg" => Some(&mut theme.git_identity_fg),
+        "git_dirty_submodules_bg" => Some(&mut theme.git_dirty_submodules_bg),
+        "git_dirty_submodules_fg" => Some(&mut theme.git_dirty_submodules_fg),
+        "git_unknown_bg" => Some(&mut theme.git_unknown_bg),
+        "git_unknown_fg" => Some(&mut theme.git_unknown_fg),
+        "git_worktree_bg" => Some(&mut theme.git_worktree_bg),
+        "git_worktree_fg" => Some(&mut theme.git_worktree_fg),
+        "git_submodule_bg" => Some(&mut theme.git_submodule_bg),
+        "git_submodule_fg" => Some(&mut theme.git_submodule_fg),
+
+        "hg_clean_bg" => Some(&mut theme.hg_clean_bg),
+        "hg_clean_fg" => Some(&mut theme.hg_clean_fg),
+        "hg_dirty_bg" => Some(&mut theme.hg_dirty_bg),
+        "hg_dirty_fg" => Some(&mut theme.hg_dirty_fg),
+
+        "jj_bg" => Some(&mut theme.jj_bg),
+        "jj_fg" => Some(&mut theme.jj_fg),
+        "jj_conflict_bg" => Some(&mut theme.jj_conflict_bg),
+        "jj_conflict_fg" => Some(&mut theme.jj_conflict_fg),
+
+        "vcs_clean_bg" => Some(&mut theme.vcs_clean_bg),
+        "vcs_clean_fg" => Some(&mut theme.vcs_clean_fg),
+        "vcs_dirty_bg" => Some(&mut theme.vcs_dirty_bg),
+        "vcs_dirty_fg" => Some(&mut theme.vcs_dirty_fg),
 
         "cmd_passed_bg" => Some(&mut theme.cmd_passed_bg),
         "cmd_passed_fg" => Some(&mut theme.cmd_passed_fg),
@@ -253,6 +472,8 @@ fn theme_index_u8<'a>(theme: &'a mut Theme, name: &str) -> Option<&'a mut u8> {
 fn theme_index_char<'a>(theme: &'a mut Theme, name: &str) -> Option<&'a mut char> {
     match name {
         "ssh_char" => Some(&mut theme.ssh_char),
+        "cwd_deleted_char" => Some(&mut theme.cwd_deleted_char),
+        "cwd_symlink_char" => Some(&mut theme.cwd_symlink_char),
         "ro_char" => Some(&mut theme.ro_char),
 
         "git_ahead_char" => Some(&mut theme.git_ahead_char),
@@ -261,6 +482,21 @@ fn theme_index_char<'a>(theme: &'a mut Theme, name: &str) -> Option<&'a mut char
         "git_notstaged_char" => Some(&mut theme.git_notstaged_char),
         "git_untracked_char" => Some(&mut theme.git_untracked_char),
         "git_conflicted_char" => Some(&mut theme.git_conflicted_char),
+        "git_identity_char" => Some(&mut theme.git_identity_char),
+        "git_dirty_submodules_char" => Some(&mut theme.git_dirty_submodules_char),
+        "git_unknown_char" => Some(&mut theme.git_unknown_char),
+        "git_worktree_char" => Some(&mut theme.git_worktree_char),
+        "git_submodule_char" => Some(&mut theme.git_submodule_char),
+        "git_shallow_char" => Some(&mut theme.git_shallow_char),
+        "git_sparse_char" => Some(&mut theme.git_sparse_char),
+
+        "hg_bookmark_char" => Some(&mut theme.hg_bookmark_char),
+
+        "jj_char" => Some(&mut theme.jj_char),
+        "jj_conflict_char" => Some(&mut theme.jj_conflict_char),
+
+        "vcs_svn_char" => Some(&mut theme.vcs_svn_char),
+        "vcs_fossil_char" => Some(&mut theme.vcs_fossil_char),
 
         _ => None
     }
@@ -0,0 +1,50 @@
+use crate::{
+    format::{escape, file_url, fill, Osc},
+    segments,
+    Powerline
+};
+use std::env;
+
+/// Fill in the --title template. `{user}`, `{host}`, `{cwd}` (with `~` for
+/// the home directory) and `{branch}` are replaced.
+fn expand(p: &mut Powerline, template: &str) -> String {
+    fill(template, |name| match name {
+        "user" => Some(env::var("USER").unwrap_or_default()),
+        "host" => Some(segments::hostname().unwrap_or_default()),
+        "cwd" => {
+            let (cwd, _) = segments::logical_cwd();
+            let mut string = cwd.to_string_lossy().into_owned();
+            if let Some(home) = dirs::home_dir() {
+                if let Ok(path) = cwd.strip_prefix(&home) {
+                    string = if path.as_os_str().is_empty() {
+                        String::from("~")
+                    } else {
+                        format!("~/{}", path.to_string_lossy())
+                    };
+                }
+            }
+            Some(string)
+        },
+        "branch" => Some(segments::branch_name(p).unwrap_or_default()),
+        _ => None
+    })
+}
+
+/// Print the window title (OSC 0) from the template
+pub fn print_title(p: &mut Powerline, template: &str) {
+    // Control characters would end the sequence early
+    let mut title: String = expand(p, template).chars().filter(|c| !c.is_control()).collect();
+    escape(p.shell, &mut title);
+    print!("{}", Osc(p.shell, 0, &title));
+}
+
+/// Report the current directory (OSC 7) so terminals can open new tabs in it
+pub fn print_cwd(p: &Powerline) {
+    let (cwd, deleted) = segments::logical_cwd();
+    if deleted {
+        return;
+    }
+    let mut url = file_url(&segments::hostname().unwrap_or_default(), &cwd);
+    escape(p.shell, &mut url);
+    print!("{}", Osc(p.shell, 7, &url));
+}